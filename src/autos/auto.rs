@@ -1,11 +1,23 @@
 use core::f64;
 use std::{fmt::Debug, time::Instant, vec::Vec};
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
     autos::{
+        branch::Jump,
         chassis::Chassis,
-        path::{LinearInterp, PathSegment},
-    }, log_debug, util::dot
+        exit::{ExitCondition, ExitTimers},
+        path::{LinearInterp, Lookahead, PathSegment, Ramsete, Side, Turn, TurnTarget},
+        profile::{Constraints, MotionProfile},
+        runner::{MotionEvent, MotionState},
+        trajectory::Trajectory,
+        sequence::{RunningSequence, Sequence},
+        transform::Transform,
+        trigger::{Readings, Trigger},
+    },
+    clock::{RealClock, SharedClock},
+    util::dot,
 };
 
 /// Types of Autos that can be created/used
@@ -30,10 +42,33 @@ pub(crate) enum Autos {
     Recorded, // Use the most recently recorded auto
 }
 
+impl Autos {
+    /// Every type of auto, in the order they're declared
+    pub const ALL: [Autos; 14] = [
+        Autos::LeftQual,
+        Autos::LeftElims,
+        Autos::LeftCounterQual,
+        Autos::LeftCounterElims,
+        Autos::RightQual,
+        Autos::RightElims,
+        Autos::RightCounterQual,
+        Autos::RightCounterElims,
+        Autos::Solo,
+        Autos::CounterSolo,
+        Autos::Skills,
+        Autos::SkillsDriver,
+        Autos::None,
+        Autos::Recorded,
+    ];
+
+    /// Name of the file this auto is loaded from on the SD card
+    pub fn file_name(&self) -> String { format!("auto_{self:?}.json") }
+}
+
 /// A miscellaneous action seperate from motion control
 /// Includes motors, solenoids and position tracking
 #[allow(unused)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub(crate) enum Action {
     ToggleMatchload,
     ToggleDescore,
@@ -45,6 +80,30 @@ pub(crate) enum Action {
     DistanceReset(u8),
 }
 
+impl Action {
+    /// The action that puts things back the way they were before this one, if
+    /// there is one
+    pub fn undo(&self) -> Option<Action> {
+        match self {
+            Action::ToggleMatchload => Some(Action::ToggleMatchload),
+            Action::ToggleDescore => Some(Action::ToggleDescore),
+            Action::SpinIntake(_) => Some(Action::StopIntake),
+            Action::SpinIndexer(_) => Some(Action::StopIndexer),
+            _ => None,
+        }
+    }
+}
+
+/// Why the robot stopped following a `PathSegment`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum SegmentExit {
+    Settled, // The error stayed small for long enough
+    Crossed, // The robot drove past the target point
+    Timeout, // The segment ran out of time
+    Stopped, // The robot stopped getting any closer
+    Stalled, // The robot pushed against something it couldn't move
+}
+
 /// The main Auto struct - holds all the information relevant to the
 /// current state of the auto and the path and actions associated with the
 /// auto \
@@ -54,46 +113,118 @@ pub(crate) enum Action {
 ///  `spline_t: f64` (internal) - how far along has the robot traveled along the path \
 ///  `curve_t: f64` (internal) - how long along has the robot traveled along the current curve \
 ///  `current_curve` (internal) - what `PathSegment` is the robot on \
-///  `actions: Vec<(Action, f64)>` - list of all the actions in the auto, at what times (in ms) \
-///  `current_action: usize` (internal) - what action is the auto on \
-///  `timeout_start: Instant` (internal) - when did the last motion start \
-///  `wait_start: Instant` (internal) - when did the wait period for the last motion start \
-///  `waiting: bool` (internal) - is the robot waiting in place or not \
+///  `actions: Vec<(Action, Trigger)>` - list of all the actions in the auto and when to run them \
+///  `fired: Vec<bool>` (internal) - which actions have already run \
+///  `sequences: Vec<(Sequence, Trigger)>` - actions spread out over time, run alongside the path once triggered \
+///  `sequences_fired: Vec<bool>` (internal) - which sequences have already been started \
+///  `running: Vec<RunningSequence>` (internal) - sequences that have started but not finished \
+///  `jumps: Vec<Jump>` - where to go after a segment instead of the next one, for branches \
+///  `visited: Vec<bool>` (internal) - which segments have been started, so that skipped ones don't run their actions \
+///  `travelled: f64` (internal) - length of the segments driven before the current one (in) \
+///  `readings: Readings` (internal) - sensor values from the last time actions were polled \
+///  `auto_start: Instant` (internal) - when did the auto start \
+///  `segment_start: Instant` (internal) - when did the current curve start \
+///  `motion_start: Instant` (internal) - when did the current curve, or the wait after it, start \
+///  `last_update: Instant` (internal) - when did the chassis last update \
 ///  `close: bool` (internal) - are we close to the end of the motion \
-///  `exit_state: u8` (internal) - have we exited a curve or a heading correction motion or not
+///  `state: MotionState` (internal) - what the robot is doing with the current curve \
+///  `exits: Vec<(usize, SegmentExit)>` (internal) - every segment that has been exited so far and why \
+///  `exit_timers: ExitTimers` (internal) - how long the current segment's exit conditions have been met \
+///  `exit_conditions: Vec<(usize, bool, usize)>` (internal) - every segment's own exit condition that was met so far, which segment, whether it was the heading one and which condition fired \
+///  `profile: Option<MotionProfile>` (internal) - motion profile for the current curve, generated when it starts \
+///  `trajectory: Option<Trajectory>` (internal) - timed path for RAMSETE to track, generated when the curve starts \
+///  `clock: SharedClock` (internal) - where the auto gets the time from
 #[derive(Debug)]
 pub(crate) struct Auto {
     pub start_pose: (f64, f64, f64) = (0.0, 0.0, 0.0),
     pub spline: Vec<PathSegment> = vec![],
     pub(crate) curve_t: f64 = 0.0,
     pub(crate) current_curve: usize = 0,
-    pub actions: Vec<(Action, f64)> = vec![],
-    pub current_action: usize = 0,
+    pub actions: Vec<(Action, Trigger)> = vec![],
+    pub fired: Vec<bool> = vec![],
+    pub sequences: Vec<(Sequence, Trigger)> = vec![],
+    pub sequences_fired: Vec<bool> = vec![],
+    pub running: Vec<RunningSequence> = vec![],
+    pub jumps: Vec<Jump> = vec![],
+    pub visited: Vec<bool> = vec![],
+    pub travelled: f64 = 0.0,
+    pub readings: Readings = Readings { .. },
+    pub auto_start: Instant,
+    pub segment_start: Instant,
     pub motion_start: Instant,
     pub last_update: Instant,
     pub close: bool = false,
-    pub exit_state: u8 = 0,
+    pub state: MotionState = MotionState::Driving,
+    pub exits: Vec<(usize, SegmentExit)> = vec![],
+    pub exit_timers: ExitTimers = ExitTimers { .. },
+    pub exit_conditions: Vec<(usize, bool, usize)> = vec![],
+    pub profile: Option<MotionProfile> = None,
+    pub trajectory: Option<Trajectory> = None,
+    pub clock: SharedClock,
 }
 
 impl Auto {
     pub fn new() -> Self {
+        let clock = RealClock::shared();
         Self {
             start_pose: (0.0, 0.0, 0.0),
             spline: vec![],
             curve_t: 0.0,
             current_curve: 0,
             actions: vec![],
-            current_action: 0,
-            motion_start: Instant::now(),
-            last_update: Instant::now(),
+            fired: vec![],
+            sequences: vec![],
+            sequences_fired: vec![],
+            running: vec![],
+            jumps: vec![],
+            visited: vec![],
+            travelled: 0.0,
+            readings: Readings { .. },
+            auto_start: clock.now(),
+            segment_start: clock.now(),
+            motion_start: clock.now(),
+            last_update: clock.now(),
             close: false,
-            exit_state: 0,
+            state: MotionState::Driving,
+            exits: vec![],
+            exit_timers: ExitTimers { .. },
+            exit_conditions: vec![],
+            profile: None,
+            trajectory: None,
+            clock,
         }
     }
 
     pub fn add_curves(&mut self, mut curves: Vec<PathSegment>) { self.spline.append(&mut curves); }
 
-    pub fn add_actions(&mut self, mut actions: Vec<(Action, f64)>) { self.actions.append(&mut actions); }
+    pub fn add_actions(&mut self, mut actions: Vec<(Action, Trigger)>) { self.actions.append(&mut actions); }
+
+    /// Add `part` onto the end of the path, moving its actions' triggers from its
+    /// own segments to where its segments end up
+    pub fn append(&mut self, mut part: Auto) {
+        let (segments, distance) = (self.spline.len(), self.path_distance(self.spline.len()));
+        self.spline.append(&mut part.spline);
+        self.actions.extend(part.actions.into_iter().map(|(action, trigger)| (action, trigger.offset(segments, distance))));
+        self.sequences.extend(part.sequences.into_iter().map(|(sequence, trigger)| (sequence, trigger.offset(segments, distance))));
+        self.jumps.extend(part.jumps.into_iter().map(|jump| jump.offset(segments)));
+    }
+
+    /// Add a reusable piece of an auto, built by `fragment` as if it was its own
+    /// auto starting where this one ends, with its actions timed by its own
+    /// segments \
+    /// `transform` moves the fragment to another part of the field after it's
+    /// built, so fragments are written for one side of the field only
+    pub fn include(&mut self, fragment: impl FnOnce(&mut Auto), transform: Option<Transform>) {
+        let mut part = Auto::new();
+        // Every transform undoes itself, so this is where the fragment starts
+        // before it's moved
+        part.start_pose = transform.map_or(self.end_pose(), |transform| transform.pose(self.end_pose()));
+        fragment(&mut part);
+        if let Some(transform) = transform {
+            part.transform(transform);
+        }
+        self.append(part);
+    }
 
     pub fn move_to_pose(&mut self, x: f64, y: f64, theta: f64) -> &mut PathSegment {
         let start_pos = if self.spline.is_empty() {
@@ -111,58 +242,110 @@ impl Auto {
         self.spline.last_mut().unwrap()
     }
 
-    pub fn add_action(&mut self, action: Action, time: f64) { self.actions.push((action, time)); }
+    /// Turn in place to face `theta` degrees
+    pub fn turn_to_heading(&mut self, theta: f64) -> &mut PathSegment { self.add_turn(Turn { target: TurnTarget::Heading(theta), .. }, theta) }
 
-    pub fn wait_for(&mut self, time: f64) {
-        let pos = if self.spline.is_empty() {
-            (self.start_pose.0, self.start_pose.1)
-        } else {
-            self.spline.last().unwrap().curve.sample(1.0)
-        };
-        let heading = if self.spline.is_empty() { self.start_pose.2 } else { self.spline.last().unwrap().end_heading };
+    /// Turn in place to face the point (`x`, `y`), or face away from it if the
+    /// segment is reversed
+    #[allow(unused)]
+    pub fn turn_to_point(&mut self, x: f64, y: f64) -> &mut PathSegment {
+        let pos = self.end_pos();
+        let heading = (-(y - pos.1).atan2(x - pos.0) + f64::consts::FRAC_PI_2).to_degrees().rem_euclid(360.0);
+        self.add_turn(Turn { target: TurnTarget::Point((x, y)), .. }, heading)
+    }
+
+    /// Turn to face `theta` degrees by only driving one side of the drivetrain,
+    /// pivoting around the `locked` side
+    #[allow(unused)]
+    pub fn swing_to_heading(&mut self, theta: f64, locked: Side) -> &mut PathSegment {
+        self.add_turn(Turn { target: TurnTarget::Heading(theta), swing: Some(locked), .. }, theta)
+    }
+
+    fn add_turn(&mut self, turn: Turn, end_heading: f64) -> &mut PathSegment {
+        let pos = self.end_pos();
         let curve = PathSegment {
             curve: LinearInterp::new(pos, pos),
-            end_heading: heading,
-            timeout: 0.0,
-            wait_time: time,
+            end_heading,
+            turn: Some(turn),
             ..Default::default()
         };
         self.spline.push(curve);
+        self.spline.last_mut().unwrap()
+    }
+
+    /// Where the robot will be once the path so far has been driven
+    fn end_pos(&self) -> (f64, f64) {
+        let pose = self.end_pose();
+        (pose.0, pose.1)
+    }
+
+    /// Pose of the robot once the path so far has been driven, heading in degrees
+    pub fn end_pose(&self) -> (f64, f64, f64) {
+        match self.spline.last() {
+            Some(segment) => {
+                let pos = segment.curve.sample(1.0);
+                (pos.0, pos.1, segment.end_heading)
+            }
+            None => self.start_pose,
+        }
+    }
+
+    /// Run `action` once the robot is `pos` along the path, the segment index
+    /// plus how far along that segment as a fraction of its length, see
+    /// `Trigger::Position`
+    pub fn add_action(&mut self, action: Action, pos: f64) { self.add_action_when(action, Trigger::Position(pos)); }
+
+    /// Run `action` once `trigger` is met
+    pub fn add_action_when(&mut self, action: Action, trigger: Trigger) { self.actions.push((action, trigger)); }
+
+    /// Start `sequence` once the robot is `pos` along the path, see `Auto::add_action`
+    #[allow(unused)]
+    pub fn add_sequence(&mut self, sequence: Sequence, pos: f64) { self.add_sequence_when(sequence, Trigger::Position(pos)); }
+
+    /// Start `sequence` once `trigger` is met, it carries on while the robot drives
+    pub fn add_sequence_when(&mut self, sequence: Sequence, trigger: Trigger) { self.sequences.push((sequence, trigger)); }
+
+    pub fn wait_for(&mut self, time: f64) {
+        // Hold the heading we already have for `time` ms
+        let segment = self.turn_to_heading(self.end_pose().2);
+        segment.timeout = 0.0;
+        segment.wait_time = time;
+    }
+
+    /// Use `clock` for timeouts, waits and slew instead of the system clock
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+        self.reset_state();
     }
 
     pub fn reset_state(&mut self) {
         self.curve_t = 0.0;
         self.current_curve = 0;
-        self.current_action = 0;
-        self.motion_start = Instant::now();
+        self.fired = vec![false; self.actions.len()];
+        self.sequences_fired = vec![false; self.sequences.len()];
+        self.running.clear();
+        self.visited = vec![false; self.spline.len()];
+        if let Some(first) = self.visited.first_mut() {
+            *first = true;
+        }
+        self.travelled = 0.0;
+        self.readings = Readings { .. };
+        self.auto_start = self.clock.now();
+        self.segment_start = self.clock.now();
+        self.motion_start = self.clock.now();
+        self.last_update = self.clock.now();
+        self.close = false;
+        self.state = MotionState::Driving;
+        self.exits.clear();
+        self.exit_timers.reset();
+        self.exit_conditions.clear();
+        self.profile = None;
+        self.trajectory = None;
     }
 
     fn cross_track_err(&mut self, pos: (f64, f64)) -> f64 {
-        // Running t value as we try and find the closest point
-        let mut t = self.curve_t;
-        for _ in 0..5 {
-            // Calculate the point on the curve at the current value of t
-            let p = self.spline[self.current_curve].curve.sample(t);
-            // Then on the derivative of the curve
-            let dp = self.spline[self.current_curve].curve.sample_derivative(t);
-            // And finally on the second derivative of the curve
-            let ddp = self.spline[self.current_curve].curve.sample_derivative2(t);
-
-            // Now calculate the error between the point and the robot
-            let path_to_robot = (p.0 - pos.0, p.1 - pos.1);
-            // And get the dot product between that and the derivative of the curve
-            let f = dot(path_to_robot, dp);
-            // Now get the derivative of that
-            let f_prime = dot(dp, dp) + dot(path_to_robot, ddp);
-
-            // If the derivative of our (kinda) error function is close to zero exit the
-            // loop as the benefits of continuing diminish rapidly
-            if f_prime.abs() < 1E-4 {
-                break;
-            }
-            // Otherwise continue iterating
-            t -= f / f_prime;
-        }
+        // Find the closest point on the curve, starting from where we were last time
+        let mut t = self.spline[self.current_curve].curve.closest_point(pos, Some(self.curve_t));
         // Clamp our new t value to not overshoot
         t = t.clamp(self.curve_t - 0.1, self.curve_t + 0.1);
         // Store our new t value
@@ -179,6 +362,55 @@ impl Auto {
         err.0.hypot(err.1) * if dot(err, (-dp.1, dp.0)) / len_dp < 0.0 { -1.0 } else { 1.0 }
     }
 
+    /// How far along the current curve the robot is, as a fraction of its
+    /// length instead of `curve_t`
+    pub fn progress(&self) -> f64 {
+        let curve = &self.spline[self.current_curve].curve;
+        let length = curve.length();
+        if length < 1E-6 { self.curve_t.clamp(0.0, 1.0) } else { curve.distance_at(self.curve_t) / length }
+    }
+
+    /// Get every action whose trigger has been met since the last poll, in the
+    /// order they were added, marking them so that they only run once, followed
+    /// by the steps of running sequences that are due
+    pub fn poll_actions(&mut self, readings: &Readings) -> Vec<Action> {
+        // Kept for deciding which way to go at branches
+        self.readings = *readings;
+        // Actions can be added after the auto was reset
+        self.fired.resize(self.actions.len(), false);
+        self.sequences_fired.resize(self.sequences.len(), false);
+        let mut due = vec![];
+        for i in 0..self.actions.len() {
+            if !self.fired[i] && self.is_triggered(self.actions[i].1, readings) {
+                self.fired[i] = true;
+                due.push(self.actions[i].0);
+            }
+        }
+
+        let now = self.clock.now();
+        for i in 0..self.sequences.len() {
+            if !self.sequences_fired[i] && self.is_triggered(self.sequences[i].1, readings) {
+                self.sequences_fired[i] = true;
+                self.running.push(RunningSequence::new(i, now));
+            }
+        }
+        let sequences = &self.sequences;
+        self.running.retain_mut(|running| {
+            let (actions, finished) = running.advance(&sequences[running.sequence].0, now);
+            due.extend(actions);
+            !finished
+        });
+        due
+    }
+
+    /// Stop every running sequence and stop any more from starting, returning the
+    /// actions that undo what they've done so far \
+    /// Run when the auto ends or the robot is disabled so nothing is left running
+    pub fn cancel_actions(&mut self) -> Vec<Action> {
+        self.sequences_fired = vec![true; self.sequences.len()];
+        self.running.drain(..).flat_map(|running| running.undo).collect()
+    }
+
     pub fn get_timeout(&self) -> f64 { self.spline[self.current_curve].timeout }
 
     pub fn get_wait(&self) -> f64 { self.spline[self.current_curve].wait_time }
@@ -198,15 +430,265 @@ pub fn desaturate(p: (f64, f64)) -> (f64, f64) {
 }
 
 impl Chassis {
+    /// Forward speed from the current segment's motion profile, generating the
+    /// profile on the segment's first update \
+    /// Returns `None` if the segment isn't profiled or the profile has finished
+    fn profile_speed(&mut self, auto: &mut Auto) -> Option<f64> {
+        let segment = &auto.spline[auto.current_curve];
+        let constraints = segment.profile?;
+        let profile = auto.profile.get_or_insert_with(|| {
+            // Start from however fast we were already going
+            let start_vel = self.last_linear_out.abs() * self.top_speed;
+            let end_vel = if segment.chained { segment.min_speed.sample(1.0) * self.top_speed } else { 0.0 };
+            MotionProfile::new(segment.curve.length(), start_vel, end_vel, constraints)
+        });
+
+        let t = auto.clock.elapsed(auto.motion_start).as_secs_f64();
+        if t >= profile.duration() {
+            return None;
+        }
+        let target = profile.sample(t);
+        let progress = segment.curve.distance_at(auto.curve_t);
+        // Feed forward the profile's velocity and acceleration and use the linear PID (in
+        // meters) to catch up if we fall behind it, any difference between the sides is
+        // left to the steering to correct
+        let (feedforward, _) = self.feedforward(target.velocity, 0.0, target.acceleration);
+        Some(feedforward + self.linear.update((target.position - progress) / 39.37))
+    }
+
+    /// Exit for driving to the end point when the segment doesn't have its own,
+    /// once the robot drives past the end point (or the linear PID settles) \
+    /// Chained motions never slow down to settle, so they count as past it
+    /// anywhere within `chained_radius` (in)
+    fn drive_exit(chained: bool, chained_radius: f64) -> ExitCondition { ExitCondition::Crossed(if chained { chained_radius } else { 0.2 }) }
+
+    /// Adaptive pure pursuit, drive along the arc through a goal point that's
+    /// further along the path than the robot
+    fn pure_pursuit(&mut self, auto: &mut Auto, pose: (f64, f64, f64), lookahead: Lookahead) -> (f64, f64) {
+        let progress = auto.progress();
+        let segment = &auto.spline[auto.current_curve];
+        let chained = segment.chained;
+        let reverse = if segment.reversed_drive { -1.0 } else { 1.0 };
+        let target_pos = segment.curve.sample(1.0);
+        let target_dist = distance((pose.0, pose.1), target_pos);
+        let max_linear = segment.max_speed.sample(progress);
+        let min_linear = if chained { segment.min_speed.sample(progress) } else { 0.0 };
+
+        // Look further ahead the faster we're going so that we don't oscillate around
+        // the path
+        let lookahead = (lookahead.min + lookahead.speed_gain * self.last_linear_out.abs() * self.top_speed).min(lookahead.max);
+        let travelled = segment.curve.distance_at(auto.curve_t.clamp(0.0, 1.0));
+        let goal_dist = travelled + lookahead;
+        let length = segment.curve.length();
+        let goal = if goal_dist <= length {
+            segment.curve.sample(segment.curve.t_at_distance(goal_dist))
+        } else {
+            // Past the end of the path keep going in the direction that it ends in so that
+            // we line up with it instead of cutting across to the end point
+            let end_dir = segment.curve.sample_derivative(1.0);
+            let end_len = end_dir.0.hypot(end_dir.1).max(1E-6);
+            (target_pos.0 + end_dir.0 / end_len * (goal_dist - length), target_pos.1 + end_dir.1 / end_len * (goal_dist - length))
+        };
+
+        // Forward and right vectors of the robot, flipped if we are driving backwards
+        let forward_vector = (pose.2.sin() * reverse, pose.2.cos() * reverse);
+        let right_vector = (pose.2.cos() * reverse, -pose.2.sin() * reverse);
+        let robot_to_goal = (goal.0 - pose.0, goal.1 - pose.1);
+        // Curvature of the arc from the robot to the goal point, positive to the right
+        let curvature = 2.0 * dot(robot_to_goal, right_vector) / dot(robot_to_goal, robot_to_goal).max(1E-6);
+
+        let target_to_robot_vector = (pose.0 - target_pos.0, pose.1 - target_pos.1);
+        let side = dot(forward_vector, target_to_robot_vector);
+        let settled = self.linear.settled(target_dist, auto.motion_start, auto.motion_time());
+        if let Some(exit) = auto.check_exit(false, &Self::drive_exit(chained, 7.5), settled, target_dist, side > 0.0) {
+            auto.transition(MotionEvent::Arrived(exit));
+            return (0.0, 0.0);
+        }
+
+        let dt = auto.clock.elapsed(auto.last_update).as_secs_f64().max(1E-4);
+        let profile_speed = self.profile_speed(auto);
+        // Settle onto the end point with the linear PID like the PID controller does,
+        // otherwise drive as fast as we're allowed to and let the curvature steer
+        auto.close = target_dist < 4.0 && !chained;
+        let mut linear_out = if auto.close {
+            // Scale the error by how much we're facing the end point so that we back up onto
+            // it if we overshoot
+            let cos_err = dot(forward_vector, (-target_to_robot_vector.0, -target_to_robot_vector.1)) / target_dist.max(1E-6);
+            let linear = (self.linear.update(target_dist * cos_err.abs().max(0.01) * cos_err.signum() / 39.37)).clamp(-max_linear, max_linear);
+            self.linear.saturated(linear);
+            linear
+        } else {
+            // Slow down for the end of the path with the linear PID unless we're chaining
+            // into the next segment
+            let speed = profile_speed.unwrap_or(if chained { max_linear } else { max_linear.min(self.linear.update((length - travelled) / 39.37)) });
+            if profile_speed.is_none() && (speed - self.last_linear_out.abs()).abs() > (self.linear.slew * dt).abs() {
+                self.last_linear_out.abs() + self.linear.slew * dt * (speed - self.last_linear_out.abs()).signum()
+            } else {
+                speed
+            }
+            .clamp(min_linear, max_linear)
+        };
+        linear_out *= reverse;
+
+        // Turn fast enough for the wheels to follow the arc, stop steering while settling
+        let angular_out = if auto.close { 0.0 } else { (-linear_out.abs() * curvature * self.track_width / 2.0).clamp(-max_linear, max_linear) };
+
+        self.last_linear_out = linear_out;
+        self.last_angular_out = angular_out;
+        auto.last_update = auto.clock.now();
+
+        desaturate((linear_out, angular_out))
+    }
+
+    /// RAMSETE, track where the robot should be at this point in time along
+    /// the segment's trajectory and feed forward its velocities
+    fn ramsete(&mut self, auto: &mut Auto, pose: (f64, f64, f64), gains: Ramsete) -> (f64, f64) {
+        let segment = &auto.spline[auto.current_curve];
+        let trajectory = auto.trajectory.get_or_insert_with(|| {
+            // Without a profile drive at the segment's top speed, reaching it in half a
+            // second
+            let max_vel = segment.max_speed.sample(0.0).max(segment.max_speed.sample(1.0)) * self.top_speed;
+            let constraints = segment.profile.unwrap_or(Constraints::trapezoidal(max_vel, self.top_speed * 2.0));
+            let start_vel = self.last_linear_out.abs() * self.top_speed;
+            let end_vel = if segment.chained { segment.min_speed.sample(1.0) * self.top_speed } else { 0.0 };
+            Trajectory::new(segment.curve.as_ref(), &MotionProfile::new(segment.curve.length(), start_vel, end_vel, constraints), segment.reversed_drive)
+        });
+
+        // Hand over to the heading correction as soon as we run out of time, so the
+        // segment always takes as long as the trajectory says it will
+        let t = auto.clock.elapsed(auto.motion_start).as_secs_f64();
+        if t >= trajectory.duration() {
+            auto.transition(MotionEvent::Arrived(SegmentExit::Settled));
+            return (0.0, 0.0);
+        }
+        let reference = trajectory.sample(t);
+
+        // The trajectory decides when we're done, but the segment can still have its
+        // own exit condition for stopping early
+        let target_pos = segment.curve.sample(1.0);
+        let reverse = if segment.reversed_drive { -1.0 } else { 1.0 };
+        let side = dot((pose.2.sin() * reverse, pose.2.cos() * reverse), (pose.0 - target_pos.0, pose.1 - target_pos.1));
+        if let Some(exit) = auto.check_exit(false, &ExitCondition::Any(vec![]), None, distance((pose.0, pose.1), target_pos), side > 0.0) {
+            auto.transition(MotionEvent::Arrived(exit));
+            return (0.0, 0.0);
+        }
+
+        // Error in the robot's frame (in meters), forwards and to the left, and the
+        // heading error counter-clockwise
+        let (dx, dy) = ((reference.pose.0 - pose.0) / 39.37, (reference.pose.1 - pose.1) / 39.37);
+        let forward_err = dx * pose.2.sin() + dy * pose.2.cos();
+        let left_err = -dx * pose.2.cos() + dy * pose.2.sin();
+        let mut angular_err = (pose.2 - reference.pose.2).rem_euclid(f64::consts::TAU);
+        if angular_err > f64::consts::PI {
+            angular_err -= f64::consts::TAU;
+        }
+
+        // RAMSETE works counter-clockwise in m/s and rad/s
+        let velocity = reference.velocity / 39.37;
+        let angular_velocity = -reference.angular_velocity;
+        let k = 2.0 * gains.zeta * (angular_velocity * angular_velocity + gains.b * velocity * velocity).sqrt();
+        let sinc = if angular_err.abs() < 1E-6 { 1.0 } else { angular_err.sin() / angular_err };
+        let linear = velocity * angular_err.cos() + k * forward_err;
+        let angular = angular_velocity + k * angular_err + gains.b * velocity * sinc * left_err;
+
+        // Feed forward the wheel speeds, plus the voltage needed to keep up with the
+        // trajectory's acceleration
+        let (linear_out, angular_out) = self.feedforward(linear * 39.37, angular, reference.acceleration);
+
+        self.last_linear_out = linear_out;
+        self.last_angular_out = angular_out;
+        auto.last_update = auto.clock.now();
+
+        desaturate((linear_out, angular_out))
+    }
+
+    /// Turn in place, or swing around one side, until the segment's `Turn` is
+    /// facing its target
+    fn turn(&mut self, auto: &mut Auto, pose: (f64, f64, f64)) -> (f64, f64) {
+        let (motion_start, motion_time) = (auto.motion_start, auto.motion_time());
+        let segment = &mut auto.spline[auto.current_curve];
+        let Some(target) = segment.turn.as_ref().map(|turn| turn.target) else {
+            return (0.0, 0.0);
+        };
+        let target_heading = match target {
+            TurnTarget::Heading(heading) => heading.to_radians(),
+            TurnTarget::Point(point) => -(point.1 - pose.1).atan2(point.0 - pose.0) + f64::consts::FRAC_PI_2 + if segment.reversed_drive { f64::consts::PI } else { 0.0 },
+        }
+        .rem_euclid(f64::consts::TAU);
+        // Keep the end heading up to date with where we're actually facing the point from
+        segment.end_heading = target_heading.to_degrees();
+
+        let chained = segment.chained;
+        let mut max_angular = segment.max_speed.sample(1.0);
+        let min_angular = if chained { segment.min_speed.sample(1.0) } else { 0.0 };
+        let end_heading_err = segment.end_heading_err;
+        let turn = segment.turn.as_mut().unwrap();
+        let swing = turn.swing;
+        // Chained turns only need to get close, otherwise we also have to have slowed
+        // down (to under 30 deg/s) so that we don't coast past the target, or have
+        // settled near it
+        let settle = if chained {
+            ExitCondition::Within(end_heading_err, 0.0)
+        } else {
+            ExitCondition::All(vec![ExitCondition::Within(turn.tolerance, 0.0), ExitCondition::Slower(30.0, 0.0)])
+        };
+
+        // Angular error normalized between [-pi, pi]
+        let mut angular_err = (pose.2 - target_heading).rem_euclid(f64::consts::TAU);
+        if angular_err > f64::consts::PI {
+            angular_err -= f64::consts::TAU;
+        }
+        let overshot = self.last_angular_out != 0.0 && angular_err.signum() != self.last_angular_out.signum();
+        let settled = if chained { None } else { turn.pid.as_mut().unwrap_or(&mut self.angular).settled(angular_err.abs().to_degrees(), motion_start, motion_time) };
+        if let Some(exit) = auto.check_exit(true, &settle, settled, angular_err.to_degrees(), overshot) {
+            auto.transition(MotionEvent::Finished(exit));
+            self.last_angular_out = 0.0;
+            return (0.0, 0.0);
+        }
+        let dt = auto.clock.elapsed(auto.last_update).as_secs_f64().max(1E-4);
+        let pid = auto.spline[auto.current_curve].turn.as_mut().and_then(|turn| turn.pid.as_mut()).unwrap_or(&mut self.angular);
+
+        // Slow down for the last 30 degrees like the end of a path does, unless we're
+        // chaining into the next motion
+        if angular_err.abs() <= (30.0_f64).to_radians() && !chained {
+            max_angular = max_angular.min(self.last_angular_out.abs().max(0.25));
+        }
+
+        let mut angular = pid.update(angular_err);
+        if (angular - self.last_angular_out).abs() > (pid.slew * dt).abs() {
+            angular = self.last_angular_out + pid.slew * dt * (angular - self.last_angular_out).signum();
+        }
+        angular = angular.clamp(-max_angular, max_angular);
+        if angular.abs() < min_angular {
+            angular = angular_err.signum() * min_angular;
+        }
+        pid.saturated(angular);
+
+        self.last_linear_out = 0.0;
+        self.last_angular_out = angular;
+        auto.last_update = auto.clock.now();
+
+        // A swing turn only drives one side, twice as hard so that the robot turns about
+        // as fast as it would in place
+        match swing {
+            None => desaturate((0.0, angular)),
+            Some(Side::Left) => (0.0, (2.0 * angular).clamp(-1.0, 1.0)),
+            Some(Side::Right) => ((-2.0 * angular).clamp(-1.0, 1.0), 0.0),
+        }
+    }
+
     pub fn update(&mut self, auto: &mut Auto) -> (f64, f64) {
         let pose = self.pose.read().pose;
+        if !auto.state.is_done() && auto.spline[auto.current_curve].turn.is_some() {
+            return self.turn(auto, pose);
+        }
         let efa = auto.cross_track_err((pose.0, pose.1));
         let target_pos = auto.spline[auto.current_curve].curve.sample(1.0);
         let target_dist = distance((pose.0, pose.1), target_pos);
 
-        if auto.exit_state >= 2 { return (0.0, 0.0); }
+        if auto.state.is_done() { return (0.0, 0.0); }
 
-        if target_dist < 0.05 || auto.exit_state == 1 {
+        if target_dist < 0.05 || matches!(auto.state, MotionState::Turning(_)) {
             // Minimum anglar velocity for chained motions, maximum angular velocity from
             // parameters
             let min_angular = if auto.spline[auto.current_curve].chained {
@@ -232,19 +714,23 @@ impl Chassis {
             // Get the angular PID value based on our normalized error
             let mut angular = self.angular.update(angular_err);
             // Early exit if our angular error is small enough
-            if angular_err.abs() <= (0.25_f64).to_radians() && !auto.spline[auto.current_curve].chained {
-                auto.exit_state = 2;
-                angular = 0.0;
+            // Use a larger (user defined) early exit parameter if we are chaining motions,
+            // and exit if the minimum angular velocity already carried us past the target
+            // heading since the window can be smaller than a single update's worth of turning
+            let settle = if auto.spline[auto.current_curve].chained {
+                ExitCondition::Any(vec![ExitCondition::Within(auto.spline[auto.current_curve].end_heading_err, 0.0), ExitCondition::Crossed(f64::INFINITY)])
+            } else {
+                ExitCondition::Within(0.25, 0.0)
             };
-            // Use a larger (user defined) early exit parameter if we are chaining motions
-            if angular_err.abs() <= auto.spline[auto.current_curve].end_heading_err.to_radians() && auto.spline[auto.current_curve].chained {
-                auto.exit_state = 2;
+            let overshot = matches!(auto.state, MotionState::Turning(_)) && self.last_angular_out.abs() >= min_angular && angular_err.signum() != self.last_angular_out.signum();
+            if let Some(exit) = auto.check_exit(true, &settle, None, angular_err.to_degrees(), overshot) {
+                auto.transition(MotionEvent::Finished(exit));
                 angular = 0.0;
             };
 
             // Calculate delta time for slew, limit it to a minimum of 100 microseconds if
             // it's too small
-            let dt = auto.last_update.elapsed().as_secs_f64().max(1E-4);
+            let dt = auto.clock.elapsed(auto.last_update).as_secs_f64().max(1E-4);
             // Make sure that our angular PID respects our defined slew value
             if (angular - self.last_angular_out).abs() > (self.angular.slew * dt).abs() {
                 angular = self.last_angular_out + (self.angular.slew * dt * (angular - self.last_angular_out).signum());
@@ -257,6 +743,7 @@ impl Chassis {
             if angular.abs() < min_angular && auto.spline[auto.current_curve].chained {
                 angular = angular_err.signum() * min_angular;
             }
+            self.angular.saturated(angular);
 
             // Update the last angular PID value and force the last linear PID value to 0.0
             // since we aren't moving linearly
@@ -264,25 +751,32 @@ impl Chassis {
             self.last_angular_out = angular;
 
             // Update delta time for next time
-            auto.last_update = Instant::now();
+            auto.last_update = auto.clock.now();
 
             // Output a combination of linear and angular PID that respects the maxmimum
             // motor voltage
             return desaturate((0.0, angular));
         }
 
+        if let Some(gains) = auto.spline[auto.current_curve].ramsete {
+            return self.ramsete(auto, pose, gains);
+        }
+        if let Some(lookahead) = auto.spline[auto.current_curve].pure_pursuit {
+            return self.pure_pursuit(auto, pose, lookahead);
+        }
+
         if auto.spline[auto.current_curve].curve.curve_type() == 0 && !auto.spline[auto.current_curve].force_stanley {
             // Maximum linear PID value; Based on arguments and settling distance
-            let mut max_linear = auto.spline[auto.current_curve].max_speed.sample(auto.curve_t);
+            let mut max_linear = auto.spline[auto.current_curve].max_speed.sample(auto.progress());
             // Minimum linear PID value; Only used during a motion chain
             let min_linear = if auto.spline[auto.current_curve].chained {
-                auto.spline[auto.current_curve].min_speed.sample(auto.curve_t)
+                auto.spline[auto.current_curve].min_speed.sample(auto.progress())
             } else {
                 0.0
             };
             // Maximum angular PID value; Based on arguments and settling distance once
             // again
-            let mut max_angular = auto.spline[auto.current_curve].max_speed.sample(auto.curve_t);
+            let mut max_angular = auto.spline[auto.current_curve].max_speed.sample(auto.progress());
 
             // If the robot is close to the target point, lower the maximum linear and
             // angular PID values Don't settle if we are chaining the motion
@@ -292,9 +786,20 @@ impl Chassis {
                 max_angular = self.last_angular_out.abs().max(4.7);
             }
 
+            // Steer towards a carrot point behind the target along the end heading when
+            // using boomerang, it slides onto the target as we get closer
+            let aim_pos = match auto.spline[auto.current_curve].boomerang {
+                Some(lead) if !auto.close => {
+                    let end_heading = auto.spline[auto.current_curve].end_heading.to_radians();
+                    let offset = lead * target_dist * if auto.spline[auto.current_curve].reversed_drive { -1.0 } else { 1.0 };
+                    (target_pos.0 - offset * end_heading.sin(), target_pos.1 - offset * end_heading.cos())
+                }
+                _ => target_pos,
+            };
+
             // Angular error in radians, if we are going in reverse flip it by 180 degrees
             // (PI radians), then normalize between [-pi, pi]
-            let target_heading = (-(target_pos.1 - pose.1).atan2(target_pos.0 - pose.0) + f64::consts::FRAC_PI_2).rem_euclid(f64::consts::TAU);
+            let target_heading = (-(aim_pos.1 - pose.1).atan2(aim_pos.0 - pose.0) + f64::consts::FRAC_PI_2).rem_euclid(f64::consts::TAU);
             let mut angular_err =
                 (pose.2 - target_heading + if auto.spline[auto.current_curve].reversed_drive { f64::consts::PI } else { 0.0 }).rem_euclid(f64::consts::TAU);
             if angular_err > f64::consts::PI {
@@ -306,16 +811,19 @@ impl Chassis {
 
             // Get the forward vector of the robot and the vector between the robot's
             // position and the target position
-            let forward_vector = (pose.2.cos(), pose.2.sin());
+            let forward_vector = (pose.2.sin(), pose.2.cos());
             let target_to_robot_vector = (pose.0 - target_pos.0, pose.1 - target_pos.1);
             // Use a dot product to compare the forwards vector and the target to robot
             // vector If we cross the target, the dot product should be positive
             // and we can exit If we are reversed, flip the value to represent
             // the 180 deg flip in rotation of the robot
             let side = dot(forward_vector, target_to_robot_vector) * if auto.spline[auto.current_curve].reversed_drive { -1.0 } else { 1.0 };
+            // Chained motions never slow down to settle, so count them as crossed anywhere
+            // within the radius where unchained motions would start settling
             // Exit the loop if either the timeouts expire or we go past the target point
-            if self.linear.update_timeouts(linear_err) || (side > 0.0 && target_dist < 0.2) {
-                auto.exit_state = 1;
+            let settled = self.linear.settled(linear_err, auto.motion_start, auto.motion_time());
+            if let Some(exit) = auto.check_exit(false, &Self::drive_exit(auto.spline[auto.current_curve].chained, 4.0), settled, linear_err, side > 0.0) {
+                auto.transition(MotionEvent::Arrived(exit));
                 return (0.0, 0.0);
             };
 
@@ -328,15 +836,26 @@ impl Chassis {
             // Calculate delta time for slew calculations, clamp it to a minimum of 100
             // microseconds if it gets too small Should be around 25 ms (main
             // loop update speed)
-            let dt = auto.last_update.elapsed().as_secs_f64().max(1E-4); // 1E-4 is 100 microseconds
-
-            // Get the linear PID value using the linear error converted to meters
-            let mut linear_out = self.linear.update(linear_err / 39.37);
+            let dt = auto.clock.elapsed(auto.last_update).as_secs_f64().max(1E-4); // 1E-4 is 100 microseconds
+
+            // Get the linear PID value using the linear error converted to meters, or
+            // follow the motion profile if the segment has one
+            let profile_speed = self.profile_speed(auto);
+            // Until we're settling the error is scaled down by the heading error, so it
+            // can look inside the integral band while the target is still far away
+            if !auto.close {
+                self.linear.reset_integral();
+            }
+            let mut linear_out = match profile_speed {
+                Some(speed) => speed * cos_err,
+                None => self.linear.update(linear_err / 39.37),
+            };
             // Clamp linear PID value to the max error
             linear_out = linear_out.clamp(-max_linear, max_linear);
             // If we are accelerating or deccelerating too fast clamp the change in linear
-            // PID to the slew Ignore slew if we are settling to the target
-            linear_out = if auto.close {
+            // PID to the slew Ignore slew if we are settling to the target, or if the
+            // motion profile is already limiting our acceleration
+            linear_out = if auto.close || profile_speed.is_some() {
                 linear_out
             } else if (linear_out - self.last_linear_out).abs() > (self.linear.slew * dt).abs() {
                 self.last_linear_out + (self.linear.slew * dt * (linear_out - self.last_linear_out).signum())
@@ -345,7 +864,9 @@ impl Chassis {
             };
 
             // If the motion is chained, then clamp the lower bound of the linear PID to the
-            // minimum linear PID value we set earlier
+            // minimum linear PID value we set earlier, scaled down if we aren't facing the
+            // target so that the robot turns towards it instead of circling around it
+            let min_linear = min_linear * angular_err.cos().max(0.0);
             linear_out = if auto.close && !auto.spline[auto.current_curve].chained {
                 linear_out
             } else if auto.spline[auto.current_curve].reversed_drive {
@@ -366,12 +887,20 @@ impl Chassis {
                 angular_out
             };
 
+            // Keep the integrals from winding up against the clamps and slew
+            if profile_speed.is_none() {
+                self.linear.saturated(linear_out);
+            }
+            if !auto.close {
+                self.angular.saturated(angular_out);
+            }
+
             // Update the last linear and angular PID values
             self.last_linear_out = linear_out;
             self.last_angular_out = angular_out;
 
             // Update delta time
-            auto.last_update = Instant::now();
+            auto.last_update = auto.clock.now();
 
             // Return a combination of the linear and angular PID that respect maximum motor
             // voltage
@@ -382,16 +911,18 @@ impl Chassis {
             if theta_e > f64::consts::PI {
                 theta_e -= f64::consts::TAU;
             }
-            let mut path_vel = auto.spline[auto.current_curve].min_speed.sample(auto.curve_t).midpoint(auto.spline[auto.current_curve].max_speed.sample(auto.curve_t));
+            let profile_speed = self.profile_speed(auto);
+            let mut path_vel =
+                profile_speed.unwrap_or(auto.spline[auto.current_curve].min_speed.sample(auto.progress()).midpoint(auto.spline[auto.current_curve].max_speed.sample(auto.progress())));
             let mut sigma = if path_vel == 0.0 { theta_e } else { theta_e - (self.k * efa / path_vel).atan() };
             sigma = sigma.rem_euclid(f64::consts::TAU);
             if sigma > f64::consts::PI {
                 sigma -= f64::consts::TAU;
             }
 
-            let mut max_linear = auto.spline[auto.current_curve].max_speed.sample(auto.curve_t);
-            let min_linear = if auto.spline[auto.current_curve].chained { auto.spline[auto.current_curve].min_speed.sample(auto.curve_t) } else { 0.0 };
-            let mut max_angular = auto.spline[auto.current_curve].max_speed.sample(auto.curve_t);
+            let mut max_linear = auto.spline[auto.current_curve].max_speed.sample(auto.progress());
+            let min_linear = if auto.spline[auto.current_curve].chained { auto.spline[auto.current_curve].min_speed.sample(auto.progress()) } else { 0.0 };
+            let mut max_angular = auto.spline[auto.current_curve].max_speed.sample(auto.progress());
 
             if target_dist < 7.5 && !auto.spline[auto.current_curve].chained {
                 auto.close = true;
@@ -399,23 +930,25 @@ impl Chassis {
                 max_angular = self.last_angular_out.abs().max(4.7);
             }
 
-            let forward_vector = (pose.2.cos(), pose.2.sin());
+            let forward_vector = (pose.2.sin(), pose.2.cos());
             let target_to_robot_vector = (pose.0 - target_pos.0, pose.1 - target_pos.1);
             let side = dot(forward_vector, target_to_robot_vector) * if auto.spline[auto.current_curve].reversed_drive { -1.0 } else { 1.0 };
-
-            if self.linear.update_timeouts(target_dist) || (side > 0.0 && target_dist < 0.2) {
-                auto.exit_state = 1;
+            let settled = self.linear.settled(target_dist, auto.motion_start, auto.motion_time());
+            if let Some(exit) = auto.check_exit(false, &Self::drive_exit(auto.spline[auto.current_curve].chained, 7.5), settled, target_dist, side > 0.0) {
+                auto.transition(MotionEvent::Arrived(exit));
                 return (0.0, 0.0);
             }
 
             let cos_err = ((pose.2 - auto.spline[auto.current_curve].curve.sample_heading(auto.curve_t)).rem_euclid(f64::consts::TAU)).cos();
             path_vel *= cos_err.abs().max(0.01) * cos_err.signum();
 
-            let dt = auto.last_update.elapsed().as_secs_f64().max(1E-4);
+            let dt = auto.clock.elapsed(auto.last_update).as_secs_f64().max(1E-4);
 
-            let mut linear_out = self.linear.update(path_vel);
+            // The motion profile's speed is already a linear output, and it limits our
+            // acceleration so it doesn't need slew
+            let mut linear_out = if profile_speed.is_some() { path_vel } else { self.linear.update(path_vel) };
             linear_out = linear_out.clamp(-max_linear, max_linear);
-            linear_out = if auto.close {
+            linear_out = if auto.close || profile_speed.is_some() {
                 linear_out
             } else if (linear_out - self.last_linear_out).abs() > (self.linear.slew * dt).abs() {
                 self.last_linear_out + (self.linear.slew * dt * (linear_out - self.last_linear_out).signum())
@@ -441,12 +974,10 @@ impl Chassis {
                 angular_out
             };
 
-            log_debug!("{linear_out:.2}, {angular_out:.2}, {}", auto.close);
-
             self.last_linear_out = linear_out;
             self.last_angular_out = angular_out;
 
-            auto.last_update = Instant::now();
+            auto.last_update = auto.clock.now();
 
             desaturate((linear_out, angular_out))
         }
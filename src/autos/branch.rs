@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+use crate::autos::{auto::Auto, trigger::Condition};
+
+/// Where to go once a segment has been driven, instead of the next segment \
+/// Fields: \
+///  `from: usize` - segment the jump is taken after \
+///  `to: usize` - segment to carry on from, past the end of the path to finish \
+///  `condition: Option<Condition>` - only jump if this is true, `None` to always
+/// jump
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Jump {
+    pub from: usize,
+    pub to: usize,
+    #[serde(default)]
+    pub condition: Option<Condition>,
+}
+
+impl Jump {
+    /// Move a jump written for part of an auto to where the part's segments end up
+    pub fn offset(self, segments: usize) -> Jump { Jump { from: self.from + segments, to: self.to + segments, ..self } }
+}
+
+impl Auto {
+    /// Carry on along the segments built by `taken` if `condition` is true once
+    /// the path so far has been driven, otherwise along the ones built by
+    /// `otherwise` \
+    /// Both are built like fragments starting where the auto ends, and the
+    /// segments added afterwards start from the end of `otherwise`, so both
+    /// branches should end in the same place unless the auto ends with them
+    pub fn branch(&mut self, condition: Condition, taken: impl FnOnce(&mut Auto), otherwise: impl FnOnce(&mut Auto)) {
+        if self.spline.is_empty() {
+            // The condition is checked once a segment is done, so hold still for one
+            self.wait_for(0.0);
+        }
+        let from = self.spline.len() - 1;
+        let taken_start = self.spline.len();
+        self.include(taken, None);
+        let taken_end = self.spline.len();
+        self.include(otherwise, None);
+        let end = self.spline.len();
+
+        // A branch straight after another replaces the jump past the other's
+        // `otherwise`, which went to the same place
+        self.jumps.retain(|jump| jump.from != from);
+        let taken_to = if taken_end > taken_start { taken_start } else { end };
+        self.jumps.push(Jump { from, to: taken_to, condition: Some(condition) });
+        self.jumps.push(Jump { from, to: taken_end, condition: None });
+        // Skip over `otherwise` after taking the branch
+        if taken_end > taken_start {
+            self.jumps.push(Jump { from: taken_end - 1, to: end, condition: None });
+        }
+    }
+
+    /// Segment to drive after the current one, `None` if the auto is over \
+    /// The first jump from the current segment whose condition is true is taken
+    pub fn next_segment(&self) -> Option<usize> {
+        let jump = self.jumps.iter().find(|jump| jump.from == self.current_curve && jump.condition.is_none_or(|condition| self.condition_met(condition, &self.readings)));
+        let next = jump.map_or(self.current_curve + 1, |jump| jump.to);
+        (next < self.spline.len()).then_some(next)
+    }
+}
@@ -1,26 +1,52 @@
-use std::{
-    sync::{Arc, nonpoison::RwLock},
-    time::Instant,
+use std::sync::{Arc, nonpoison::RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    autos::exit::{ExitCondition, ExitTimers},
+    clock::SharedClock,
+    conf::Config,
+    tracking::Tracking,
 };
 
-use crate::tracking::Tracking;
+/// The gains of a `Pid`, for the ones that are kept in the config
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PidGains {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+}
 
 #[derive(Debug)]
 pub(crate) struct Pid {
     last_err: f64,
     last_deriv: f64,
     sum_err: f64,
+    last_out: f64,
     pub kp: f64,
     pub ki: f64,
     pub kd: f64,
+    /// Largest the integral term can get, as a fraction of the maximum voltage
+    pub max_integral: f64,
+    /// Only integrate while the error is smaller than this, the integral is
+    /// for the last bit of a motion that the P term is too weak to push
+    /// through, not for catching up from far away
+    pub integral_band: f64,
+    /// Clear the integral when the error changes sign so it doesn't push the
+    /// robot back past the target
+    pub sign_reset: bool,
+    /// How much of the output that couldn't be applied to take back out of
+    /// the integral, see `saturated`
+    pub back_calculation: f64,
     pub deriv_alpha: f64,
     pub slew: f64,
+    /// Settling windows for the default exit conditions, see `settle_exit`
     pub small_error: f64,
     pub small_error_timeout: f64,
-    small_timeout_start: Instant,
     pub large_error: f64,
     pub large_error_timeout: f64,
-    large_timeout_start: Instant,
+    /// How long the error has been in the settling windows, see `settled`
+    pub settle_timers: ExitTimers,
 }
 
 impl Default for Pid {
@@ -29,17 +55,21 @@ impl Default for Pid {
             last_err: 0.0,
             last_deriv: 0.0,
             sum_err: 0.0,
+            last_out: 0.0,
             kp: 4.0,
             ki: 0.0,
             kd: 20.0,
+            max_integral: 0.25,
+            integral_band: f64::INFINITY,
+            sign_reset: true,
+            back_calculation: 1.0,
             deriv_alpha: 0.7,
             slew: 75.0,
             small_error: 1.0,
             small_error_timeout: 100.0,
-            small_timeout_start: Instant::now(),
             large_error: 3.0,
             large_error_timeout: 500.0,
-            large_timeout_start: Instant::now(),
+            settle_timers: ExitTimers { .. },
         }
     }
 }
@@ -55,51 +85,126 @@ impl Pid {
             slew,
             small_error,
             small_error_timeout,
-            small_timeout_start: Instant::now(),
             large_error,
             large_error_timeout,
-            large_timeout_start: Instant::now(),
             ..Default::default()
         }
     }
 
     pub(crate) fn update(&mut self, error: f64) -> f64 {
-        self.sum_err = error + 0.95 * self.sum_err;
+        if self.sign_reset && error * self.last_err < 0.0 {
+            self.sum_err = 0.0;
+        }
+        if error.abs() <= self.integral_band {
+            self.sum_err += error;
+        } else {
+            self.sum_err = 0.0;
+        }
+        self.clamp_integral();
         let prop = self.kp * error;
         let raw_deriv = error - self.last_err;
         self.last_deriv = self.deriv_alpha * (self.last_deriv - raw_deriv) + raw_deriv;
         let deriv = self.kd * self.last_deriv;
         let int = self.ki * self.sum_err;
         self.last_err = error;
-        prop + deriv + int
+        self.last_out = prop + deriv + int;
+        self.last_out
     }
 
-    pub(crate) fn update_timeouts(&mut self, value: f64) -> bool {
-        if (value < self.small_error && self.small_timeout_start.elapsed().as_secs_f64() * 1000.0 > self.small_error_timeout)
-            || (value < self.large_error && self.large_timeout_start.elapsed().as_secs_f64() * 1000.0 > self.large_error_timeout)
-        {
-            return true;
+    /// Tell the PID what was actually applied after its last output was
+    /// clamped / slewed, so the integral doesn't keep winding up while the
+    /// output is stuck against a limit (back-calculation anti-windup) \
+    /// `back_calculation` of 1 takes all of the output that couldn't be
+    /// applied back out of the integral
+    pub(crate) fn saturated(&mut self, applied: f64) {
+        if self.ki == 0.0 {
+            return;
         }
-        if value > self.small_error {
-            self.small_timeout_start = Instant::now();
+        // Only ever unwind what the integral has built up, if the P and D terms are
+        // what's saturating there's nothing for the integral to give back
+        let unwound = self.sum_err + self.back_calculation * (applied - self.last_out) / self.ki;
+        if unwound * self.sum_err <= 0.0 {
+            self.sum_err = 0.0;
+        } else if unwound.abs() < self.sum_err.abs() {
+            self.sum_err = unwound;
         }
-        if value > self.large_error {
-            self.large_timeout_start = Instant::now();
+    }
+
+    /// Keep the integral term within `max_integral`
+    fn clamp_integral(&mut self) {
+        if self.ki != 0.0 {
+            let max_sum = self.max_integral / self.ki.abs();
+            self.sum_err = self.sum_err.clamp(-max_sum, max_sum);
         }
-        false
     }
 
+    /// Throw away the integral, for when the error isn't something it should
+    /// build up on
+    pub(crate) fn reset_integral(&mut self) { self.sum_err = 0.0; }
+
+    pub fn set_gains(&mut self, gains: PidGains) {
+        self.kp = gains.kp;
+        self.ki = gains.ki;
+        self.kd = gains.kd;
+    }
+
+    /// Exit once the error has stayed in the small or large settling window for
+    /// long enough
+    pub(crate) fn settle_exit(&self) -> ExitCondition { ExitCondition::settled(self.small_error, self.small_error_timeout, self.large_error, self.large_error_timeout) }
+
     pub(crate) fn reset(&mut self) {
         self.last_err = 0.0;
         self.sum_err = 0.0;
+        self.last_out = 0.0;
     }
 }
 
+/// Voltage one side of the drivetrain needs to drive at a speed, as a fraction
+/// of the maximum \
+/// Fields: \
+///  `ks: f64` - voltage to get the side moving at all, against static
+/// friction \
+///  `kv: f64` - voltage per in/s of speed \
+///  `ka: f64` - voltage per in/s^2 of acceleration
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Feedforward {
+    pub ks: f64,
+    pub kv: f64,
+    pub ka: f64,
+}
+
+impl Feedforward {
+    /// The top speed (76.6 in/s) and acceleration (425 in/s^2, the top speed over
+    /// the drivetrain's time constant) of a 600 rpm cartridge geared 36:48 to 3.25
+    /// in wheels, with no static friction, until the drivetrain has been
+    /// characterized
+    pub const DEFAULT: Feedforward = Feedforward { ks: 0.0, kv: 1.0 / 76.6, ka: 1.0 / 425.0 };
+
+    /// Voltage to drive at `velocity` (in/s) while speeding up at `acceleration`
+    /// (in/s^2), static friction only pushes back while the side is moving
+    pub fn calculate(&self, velocity: f64, acceleration: f64) -> f64 {
+        let static_friction = if velocity.abs() < 1E-6 { 0.0 } else { self.ks * velocity.signum() };
+        static_friction + self.kv * velocity + self.ka * acceleration
+    }
+}
+
+impl Default for Feedforward {
+    fn default() -> Self { Feedforward::DEFAULT }
+}
+
 #[derive(Debug)]
 pub(crate) struct Chassis {
     pub linear: Pid,
     pub angular: Pid,
     pub k: f64 = 1.0,
+    /// How fast the robot drives at full voltage (in/s), a 600 rpm cartridge
+    /// geared 36:48 to 3.25 in wheels
+    pub top_speed: f64 = 76.6,
+    /// Feedforward for the (left, right) sides, added to the PID outputs
+    /// whenever a motion has a velocity to follow
+    pub feedforward: (Feedforward, Feedforward) = (Feedforward::DEFAULT, Feedforward::DEFAULT),
+    /// Distance between the left and right wheels (in)
+    pub track_width: f64 = 10.37,
     pub pose: Arc<RwLock<Tracking>>,
     pub last_linear_out: f64,
     pub last_angular_out: f64,
@@ -114,9 +219,28 @@ impl Chassis {
             pose,
             last_linear_out: 0.0,
             last_angular_out: 0.0,
+            ..
         }
     }
 
+    /// The Chassis tuning the robot runs with, with the PID gains, feedforward and
+    /// track width from `conf`, shared with the sim so that the tests run the same
+    /// tuning
+    pub fn from_config(conf: &Config, pose: Arc<RwLock<Tracking>>) -> Self {
+        let (linear, angular) = (conf.pid.linear, conf.pid.angular);
+        let mut linear_pid = Pid::new(linear.kp, linear.ki, linear.kd, 0.95, 20.0, 0.25, 400.0, 1.0, 2000.0);
+        // Only integrate over the last inch, and only enough to push through friction
+        // onto the target, in case kI gets turned on in the config
+        linear_pid.integral_band = 1.0 / 39.37;
+        linear_pid.max_integral = 0.05;
+        let angular_pid = Pid::new(angular.kp, angular.ki, angular.kd, 0.95, 20.0, 0.5, 400.0, 1.5, 2000.0);
+
+        let mut chassis = Chassis::new(linear_pid, angular_pid, 0.25, pose);
+        chassis.feedforward = (conf.drivetrain.left, conf.drivetrain.right);
+        chassis.track_width = conf.drivetrain.track_width;
+        chassis
+    }
+
     pub async fn calibrate(&mut self, init_pose: (f64, f64, f64)) {
         self.reset();
         self.last_linear_out = 0.0;
@@ -128,6 +252,22 @@ impl Chassis {
         self.pose.write().reset_pose(pose);
     }
 
+    /// Feedforward for driving at `velocity` (in/s) while turning
+    /// counter-clockwise at `angular_velocity` (rad/s) and speeding up at
+    /// `acceleration` (in/s^2), as the (linear, angular) outputs that
+    /// `desaturate` takes
+    pub fn feedforward(&self, velocity: f64, angular_velocity: f64, acceleration: f64) -> (f64, f64) {
+        let turn = angular_velocity * self.track_width / 2.0;
+        let left = self.feedforward.0.calculate(velocity - turn, acceleration);
+        let right = self.feedforward.1.calculate(velocity + turn, acceleration);
+        ((left + right) / 2.0, (right - left) / 2.0)
+    }
+
+    /// Use `clock` for Tracking instead of the system clock
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.pose.write().set_clock(clock);
+    }
+
     pub fn reset(&mut self) {
         self.linear.reset();
         self.angular.reset();
@@ -0,0 +1,183 @@
+use core::f64;
+use std::time::Instant;
+
+use crate::autos::{
+    auto::{Auto, SegmentExit},
+    chassis::Pid,
+    runner::MotionEvent,
+};
+
+/// When to stop following a `PathSegment`, checked every update \
+/// While driving to the end point the error is the distance to it (in), and
+/// while turning to a heading it's the heading error (deg), so the same
+/// conditions work for both \
+/// Conditions that have to hold for a while are timed from the last update
+/// where they weren't met, and start over whenever they stop being met
+#[allow(unused)]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ExitCondition {
+    Within(f64, f64),          // Error under this (in / deg) for this long (ms), 0 ms to exit as soon as it gets there
+    Slower(f64, f64),          // Error changing slower than this (in/s / deg/s) for this long (ms)
+    Crossed(f64),              // Went past the target while the error was under this (in / deg)
+    Stalled(f64, f64, f64),    // Drive current over this (A) while the error changes slower than this (in/s / deg/s), for this long (ms)
+    Timeout(f64),              // Driving or turning for this long (ms), on top of the segment's own timeout
+    Any(Vec<ExitCondition>),   // Exit when any of these are met
+    All(Vec<ExitCondition>),   // Exit when all of these are met on the same update
+}
+
+/// What the `ExitCondition`s are checked against \
+/// Fields: \
+///  `error: f64` - distance to the end point (in) or heading error (deg) \
+///  `past: bool` - has the robot gone past the target, driving past the end
+/// point or turning past the heading \
+///  `rate: f64` - how fast the error is changing (in/s / deg/s) \
+///  `current: f64` - average current drawn by the drive motors (A) \
+///  `time: f64` - time spent driving or turning so far (ms)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ExitReadings {
+    pub error: f64 = 0.0,
+    pub past: bool = false,
+    pub rate: f64 = 0.0,
+    pub current: f64 = 0.0,
+    pub time: f64 = 0.0,
+}
+
+/// Timing for the `ExitCondition`s of the current segment \
+/// Fields: \
+///  `since: Vec<Option<f64>>` - for each condition in the order `check` visits
+/// them, when it was last not met (ms), `None` if it isn't met \
+///  `last: Option<(f64, f64)>` - error and time (ms) from the last check, for
+/// the rate and timing \
+///  `heading: bool` - were the last checks on the heading error \
+///  `start: Option<Instant>` - start of the segment the times are from, for
+/// timers that carry over between segments, see `rebase`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ExitTimers {
+    pub since: Vec<Option<f64>> = vec![],
+    pub last: Option<(f64, f64)> = None,
+    pub heading: bool = false,
+    pub start: Option<Instant> = None,
+}
+
+impl ExitTimers {
+    /// Start timing again for a new segment
+    pub fn reset(&mut self) {
+        self.since.clear();
+        self.last = None;
+        self.heading = false;
+    }
+
+    /// Keep timing into the segment that started at `start`, moving the times
+    /// so far over to be from it
+    pub fn rebase(&mut self, start: Instant) {
+        if let Some(old_start) = self.start {
+            let shift = start.saturating_duration_since(old_start).as_secs_f64() * 1000.0;
+            for since in self.since.iter_mut().flatten() {
+                *since -= shift;
+            }
+            if let Some((_, time)) = self.last.as_mut() {
+                *time -= shift;
+            }
+        }
+        self.start = Some(start);
+    }
+}
+
+impl ExitCondition {
+    /// Whichever of the PID's settling windows the error stays in for long
+    /// enough, the small window for `small_timeout` ms or the large one for
+    /// `large_timeout` ms
+    pub fn settled(small: f64, small_timeout: f64, large: f64, large_timeout: f64) -> ExitCondition {
+        ExitCondition::Any(vec![ExitCondition::Within(small, small_timeout), ExitCondition::Within(large, large_timeout)])
+    }
+
+    /// Why the segment exited if the condition is met, and which condition it
+    /// was, by its index in the order `check` visits them (the same order as
+    /// `ExitTimers::since`), timing it with `timers` \
+    /// Every condition is checked even once one is met so that none of their
+    /// timers miss an update
+    pub fn check(&self, readings: &ExitReadings, timers: &mut ExitTimers) -> Option<(SegmentExit, usize)> { self.check_from(readings, timers, &mut 0) }
+
+    fn check_from(&self, readings: &ExitReadings, timers: &mut ExitTimers, index: &mut usize) -> Option<(SegmentExit, usize)> {
+        let error = readings.error.abs();
+        let (met, held_for, exit) = match *self {
+            ExitCondition::Within(within, time) => (error < within, time, SegmentExit::Settled),
+            ExitCondition::Slower(rate, time) => (readings.rate < rate, time, SegmentExit::Stopped),
+            ExitCondition::Crossed(within) => (readings.past && error < within, 0.0, SegmentExit::Crossed),
+            ExitCondition::Stalled(current, rate, time) => (readings.current > current && readings.rate < rate, time, SegmentExit::Stalled),
+            ExitCondition::Timeout(time) => (readings.time >= time, 0.0, SegmentExit::Timeout),
+            ExitCondition::Any(ref conditions) => {
+                let exits: Vec<_> = conditions.iter().map(|condition| condition.check_from(readings, timers, index)).collect();
+                return exits.into_iter().flatten().next();
+            }
+            ExitCondition::All(ref conditions) => {
+                let exits: Vec<_> = conditions.iter().map(|condition| condition.check_from(readings, timers, index)).collect();
+                // Reported as the first condition, the rest only hold it back
+                return if exits.iter().all(Option::is_some) { exits.first().copied().flatten() } else { None };
+            }
+        };
+
+        if timers.since.len() <= *index {
+            timers.since.resize(*index + 1, None);
+        }
+        let last_time = timers.last.map_or(readings.time, |(_, time)| time);
+        let since = &mut timers.since[*index];
+        *index += 1;
+        *since = if met { Some(since.unwrap_or(last_time)) } else { None };
+        since.is_some_and(|since| readings.time - since >= held_for).then_some((exit, *index - 1))
+    }
+}
+
+impl Pid {
+    /// Has the error (in / deg) stayed in one of the settling windows for long
+    /// enough, `time` (ms) into the segment that started at `start` \
+    /// The windows are timed across segments like the rest of the PID's state,
+    /// so an error that's been in a window since the last segment counts that
+    /// time too
+    pub(crate) fn settled(&mut self, error: f64, start: Instant, time: f64) -> Option<SegmentExit> {
+        self.settle_timers.rebase(start);
+        let exit = self.settle_exit().check(&ExitReadings { error, time, .. }, &mut self.settle_timers);
+        self.settle_timers.last = Some((error, time));
+        exit.map(|(exit, _)| exit)
+    }
+}
+
+impl Auto {
+    /// Time spent on the current motion so far (ms)
+    pub fn motion_time(&self) -> f64 { self.clock.elapsed(self.motion_start).as_secs_f64() * 1000.0 }
+
+    /// Check the current segment's exit condition with the robot `error` away
+    /// from the target (in / deg) and `past` it or not, or if it doesn't have
+    /// one `default` and whether the PID has `settled` \
+    /// `heading` picks the condition for turning to the end heading instead of
+    /// driving to the end point \
+    /// Which of the segment's own conditions fired is kept in
+    /// `exit_conditions`, and a `Timeout` ends the segment the same way as its
+    /// own timeout, straight to waiting, so the caller's event is ignored
+    pub fn check_exit(&mut self, heading: bool, default: &ExitCondition, settled: Option<SegmentExit>, error: f64, past: bool) -> Option<SegmentExit> {
+        // Distances and headings can't be timed together
+        if heading != self.exit_timers.heading {
+            self.exit_timers.reset();
+            self.exit_timers.heading = heading;
+        }
+        let time = self.motion_time();
+        let rate = match self.exit_timers.last {
+            Some((last_error, last_time)) if time > last_time => (error - last_error).abs() / (time - last_time) * 1000.0,
+            // Nothing to compare against yet, so it can't have stopped
+            _ => f64::INFINITY,
+        };
+
+        let readings = ExitReadings { error, past, rate, current: self.readings.drive_current, time };
+        let segment = &self.spline[self.current_curve];
+        let condition = if heading { segment.heading_exit.as_ref() } else { segment.exit.as_ref() };
+        let exit = match condition {
+            Some(condition) => condition.check(&readings, &mut self.exit_timers).inspect(|&(_, index)| self.exit_conditions.push((self.current_curve, heading, index))),
+            None => default.check(&readings, &mut self.exit_timers).or(settled.map(|exit| (exit, 0))),
+        };
+        self.exit_timers.last = Some((error, time));
+        if let Some((SegmentExit::Timeout, _)) = exit {
+            self.transition(MotionEvent::TimedOut);
+        }
+        exit.map(|(exit, _)| exit)
+    }
+}
@@ -0,0 +1,208 @@
+use core::f64;
+use std::{
+    fmt::{self, Display},
+    fs::{read_to_string, rename, write},
+    io,
+    path::Path,
+    vec::Vec,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::autos::{
+    auto::{Action, Auto},
+    branch::Jump,
+    path::{CubicBezier, CubicPolyBezier, Curve, LinearInterp, Lookahead, PathSegment, Ramsete, Side, SpeedCurve, Turn, TurnTarget},
+    profile::Constraints,
+    sequence::Sequence,
+    trigger::Trigger,
+};
+
+/// Why an auto file couldn't be read or written
+#[derive(Debug)]
+pub(crate) enum AutoFileError {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl Display for AutoFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutoFileError::Io(e) => write!(f, "couldn't access auto file: {e}"),
+            AutoFileError::Json(e) => write!(f, "invalid auto file: {e}"),
+        }
+    }
+}
+
+/// The parameters of a `Curve`, tagged with its type
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum CurveFile {
+    Linear { a: (f64, f64), b: (f64, f64) },
+    CubicBezier { a: (f64, f64), b: (f64, f64), c: (f64, f64), d: (f64, f64) },
+    CubicPolyBezier { a: (f64, f64), b: (f64, f64), c: (f64, f64), d: (f64, f64) },
+}
+
+impl CurveFile {
+    /// Recover the parameters of `curve` from its samples and derivatives, the
+    /// curves only keep them behind `dyn Curve`
+    pub fn from_curve(curve: &dyn Curve) -> Self {
+        let scale = |p: (f64, f64), k: f64| (p.0 * k, p.1 * k);
+        let add = |p: (f64, f64), q: (f64, f64)| (p.0 + q.0, p.1 + q.1);
+        match curve.curve_type() {
+            1 => {
+                let (a, d) = (curve.sample(0.0), curve.sample(1.0));
+                CurveFile::CubicBezier { a, b: add(a, scale(curve.sample_derivative(0.0), 1.0 / 3.0)), c: add(d, scale(curve.sample_derivative(1.0), -1.0 / 3.0)), d }
+            }
+            2 => {
+                let (dd0, dd1) = (curve.sample_derivative2(0.0), curve.sample_derivative2(1.0));
+                CurveFile::CubicPolyBezier { a: scale(add(dd1, scale(dd0, -1.0)), 1.0 / 6.0), b: scale(dd0, 0.5), c: curve.sample_derivative(0.0), d: curve.sample(0.0) }
+            }
+            _ => CurveFile::Linear { a: curve.sample(0.0), b: curve.sample(1.0) },
+        }
+    }
+
+    pub fn to_curve(self) -> Box<dyn Curve> {
+        match self {
+            CurveFile::Linear { a, b } => LinearInterp::new(a, b),
+            CurveFile::CubicBezier { a, b, c, d } => CubicBezier::new(a, b, c, d),
+            CurveFile::CubicPolyBezier { a, b, c, d } => Box::new(CubicPolyBezier { a, b, c, d, .. }),
+        }
+    }
+}
+
+/// A `Turn` without its PID, which stays tuned in the code
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TurnFile {
+    pub target: TurnTarget,
+    #[serde(default)]
+    pub swing: Option<Side>,
+    pub tolerance: f64,
+}
+
+/// A `PathSegment` as it's stored in an auto file, anything left out uses
+/// `PathSegment::default`'s value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct SegmentFile {
+    pub curve: CurveFile,
+    pub min_speed: SpeedCurve,
+    pub max_speed: SpeedCurve,
+    pub end_heading: f64,
+    pub end_heading_err: f64,
+    pub reversed_drive: bool,
+    pub timeout: f64,
+    pub wait_time: f64,
+    pub chained: bool,
+    pub force_stanley: bool,
+    pub pure_pursuit: Option<Lookahead>,
+    pub ramsete: Option<Ramsete>,
+    pub boomerang: Option<f64>,
+    pub turn: Option<TurnFile>,
+    pub profile: Option<Constraints>,
+}
+
+impl Default for SegmentFile {
+    fn default() -> Self { SegmentFile::from_segment(&PathSegment::default()) }
+}
+
+impl SegmentFile {
+    pub fn from_segment(segment: &PathSegment) -> Self {
+        Self {
+            curve: CurveFile::from_curve(segment.curve.as_ref()),
+            min_speed: segment.min_speed,
+            max_speed: segment.max_speed,
+            end_heading: segment.end_heading,
+            end_heading_err: segment.end_heading_err,
+            reversed_drive: segment.reversed_drive,
+            timeout: segment.timeout,
+            wait_time: segment.wait_time,
+            chained: segment.chained,
+            force_stanley: segment.force_stanley,
+            pure_pursuit: segment.pure_pursuit,
+            ramsete: segment.ramsete,
+            boomerang: segment.boomerang,
+            turn: segment.turn.as_ref().map(|turn| TurnFile { target: turn.target, swing: turn.swing, tolerance: turn.tolerance }),
+            profile: segment.profile,
+        }
+    }
+
+    pub fn to_segment(&self) -> PathSegment {
+        PathSegment {
+            curve: self.curve.to_curve(),
+            min_speed: self.min_speed,
+            max_speed: self.max_speed,
+            end_heading: self.end_heading,
+            end_heading_err: self.end_heading_err,
+            reversed_drive: self.reversed_drive,
+            timeout: self.timeout,
+            wait_time: self.wait_time,
+            chained: self.chained,
+            force_stanley: self.force_stanley,
+            pure_pursuit: self.pure_pursuit,
+            ramsete: self.ramsete,
+            boomerang: self.boomerang,
+            turn: self.turn.map(|turn| Turn { target: turn.target, swing: turn.swing, tolerance: turn.tolerance, .. }),
+            profile: self.profile,
+            ..Default::default()
+        }
+    }
+}
+
+/// Everything needed to rebuild an `Auto`, saved as JSON on the SD card so
+/// that routes can be changed without rebuilding the program \
+/// Fields: \
+///  `start_pose: (f64, f64, f64)` - starting pose, the same as `Auto::start_pose` \
+///  `segments: Vec<SegmentFile>` - the path, in order \
+///  `actions: Vec<(Action, Trigger)>` - actions and when to do them, the same as
+/// `Auto::actions` \
+///  `sequences: Vec<(Sequence, Trigger)>` - sequences and when to start them,
+/// the same as `Auto::sequences` \
+///  `jumps: Vec<Jump>` - branches between segments, the same as `Auto::jumps`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AutoFile {
+    pub start_pose: (f64, f64, f64),
+    #[serde(default)]
+    pub segments: Vec<SegmentFile>,
+    #[serde(default)]
+    pub actions: Vec<(Action, Trigger)>,
+    #[serde(default)]
+    pub sequences: Vec<(Sequence, Trigger)>,
+    #[serde(default)]
+    pub jumps: Vec<Jump>,
+}
+
+impl AutoFile {
+    pub fn from_auto(auto: &Auto) -> Self {
+        Self { start_pose: auto.start_pose, segments: auto.spline.iter().map(SegmentFile::from_segment).collect(), actions: auto.actions.clone(), sequences: auto.sequences.clone(), jumps: auto.jumps.clone() }
+    }
+
+    pub fn to_auto(&self) -> Auto {
+        let mut auto = Auto::new();
+        auto.start_pose = self.start_pose;
+        auto.add_curves(self.segments.iter().map(SegmentFile::to_segment).collect());
+        auto.add_actions(self.actions.clone());
+        auto.sequences = self.sequences.clone();
+        auto.jumps = self.jumps.clone();
+        auto
+    }
+}
+
+impl Auto {
+    /// Read an auto saved with `Auto::save`
+    pub fn load(path: &Path) -> Result<Auto, AutoFileError> {
+        let data = read_to_string(path).map_err(AutoFileError::Io)?;
+        let file: AutoFile = serde_json::from_str(&data).map_err(AutoFileError::Json)?;
+        Ok(file.to_auto())
+    }
+
+    /// Write this auto to `path` as JSON \
+    /// The file is written next to `path` first and then moved over it, so losing
+    /// power part way through leaves the old file instead of half of the new one
+    pub fn save(&self, path: &Path) -> Result<(), AutoFileError> {
+        let data = serde_json::to_string_pretty(&AutoFile::from_auto(self)).map_err(AutoFileError::Json)?;
+        let partial = path.with_extension("json.tmp");
+        write(&partial, data).map_err(AutoFileError::Io)?;
+        rename(&partial, path).map_err(AutoFileError::Io)
+    }
+}
@@ -0,0 +1,172 @@
+use core::f64;
+use std::{
+    fmt::{self, Display},
+    fs::read_to_string,
+    io,
+    path::Path,
+    string::String,
+    vec::Vec,
+};
+
+use serde::Deserialize;
+
+use crate::autos::{
+    auto::Auto,
+    path::{CubicBezier, Curve, LinearInterp, Lookahead, PathSegment, SpeedCurve},
+};
+
+/// Marker in front of the project data in path.jerryio's exported path files
+const DATA_MARKER: &str = "#PATH.JERRYIO-DATA";
+
+/// Lookahead for following the curves, path.jerryio draws paths for pure
+/// pursuit followers
+const LOOKAHEAD: Lookahead = Lookahead::fixed(8.0);
+
+/// Why a path.jerryio export couldn't be turned into an `Auto`
+#[derive(Debug)]
+pub(crate) enum JerryioError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    MissingPath(String),      // No path with this name, empty for the first path
+    BadSegment(usize, usize), // Segment index and how many control points it had
+}
+
+impl Display for JerryioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JerryioError::Io(e) => write!(f, "couldn't read path file: {e}"),
+            JerryioError::Json(e) => write!(f, "invalid path.jerryio data: {e}"),
+            JerryioError::MissingPath(name) if name.is_empty() => write!(f, "no paths in path.jerryio data"),
+            JerryioError::MissingPath(name) => write!(f, "no path named \"{name}\" in path.jerryio data"),
+            JerryioError::BadSegment(i, n) => write!(f, "segment {i} has {n} control points, expected 2 or 4"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    paths: Vec<JerryioPath>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JerryioPath {
+    #[serde(default)]
+    name: String,
+    segments: Vec<Segment>,
+    #[serde(default)]
+    pc: PathConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PathConfig {
+    #[serde(rename = "speedLimit", default)]
+    speed_limit: SpeedLimit,
+}
+
+/// Keyframe speeds are fractions of the way between `from` and `to`, in the
+/// format's units where `maxLimit` is full speed
+#[derive(Debug, Deserialize)]
+struct SpeedLimit {
+    #[serde(rename = "maxLimit")]
+    max_limit: Limit,
+    from: f64,
+    to: f64,
+}
+
+impl Default for SpeedLimit {
+    fn default() -> Self { Self { max_limit: Limit { value: 1.0 }, from: 0.0, to: 1.0 } }
+}
+
+#[derive(Debug, Deserialize)]
+struct Limit {
+    value: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Segment {
+    controls: Vec<Control>,
+    #[serde(rename = "speedProfiles", default)]
+    speed_profiles: Vec<Keyframe>,
+}
+
+/// End points have a heading in degrees, control points don't
+#[derive(Debug, Deserialize)]
+struct Control {
+    x: f64,
+    y: f64,
+    heading: Option<f64>,
+}
+
+/// Speed from `x_pos` (fraction of the way along the segment) onwards
+#[derive(Debug, Deserialize)]
+struct Keyframe {
+    #[serde(rename = "xPos")]
+    x_pos: f64,
+    #[serde(rename = "yPos")]
+    y_pos: f64,
+}
+
+impl Auto {
+    /// Build an `Auto` from a path.jerryio export, either the path file or the
+    /// saved project, following the path named `name` or the first path if
+    /// `None` \
+    /// Two point segments become `LinearInterp`s driven to with the PID
+    /// controller and four point segments become `CubicBezier`s followed with
+    /// pure pursuit \
+    /// Speed keyframes hold until the next keyframe, even across segments, and
+    /// become each segment's `max_speed` from the speed at its start to the
+    /// speed at its end
+    pub fn from_jerryio(data: &str, name: Option<&str>) -> Result<Auto, JerryioError> {
+        let json = data.split_once(DATA_MARKER).map_or(data, |(_, json)| json);
+        let project: Project = serde_json::from_str(json.trim()).map_err(JerryioError::Json)?;
+        let path = match name {
+            Some(name) => project.paths.iter().find(|path| path.name == name),
+            None => project.paths.first(),
+        }
+        .ok_or_else(|| JerryioError::MissingPath(String::from(name.unwrap_or_default())))?;
+
+        let limit = &path.pc.speed_limit;
+        let to_speed = |y_pos: f64| ((limit.from + y_pos * (limit.to - limit.from)) / limit.max_limit.value).clamp(0.0, 1.0);
+
+        let mut auto = Auto::new();
+        let mut speed = 1.0;
+        let mut heading = 0.0;
+        for (i, segment) in path.segments.iter().enumerate() {
+            let points: Vec<(f64, f64)> = segment.controls.iter().map(|control| (control.x, control.y)).collect();
+            if i == 0 {
+                heading = segment.controls.first().and_then(|control| control.heading).unwrap_or(0.0);
+                auto.start_pose = (points.first().map_or(0.0, |p| p.0), points.first().map_or(0.0, |p| p.1), heading);
+            }
+
+            let mut keyframes: Vec<&Keyframe> = segment.speed_profiles.iter().collect();
+            keyframes.sort_by(|a, b| a.x_pos.total_cmp(&b.x_pos));
+            let start_speed = keyframes.iter().take_while(|keyframe| keyframe.x_pos <= 1E-6).last().map_or(speed, |keyframe| to_speed(keyframe.y_pos));
+            speed = keyframes.last().map_or(start_speed, |keyframe| to_speed(keyframe.y_pos));
+
+            let (curve, pure_pursuit): (Box<dyn Curve>, _) = match points[..] {
+                [a, b] => (LinearInterp::new(a, b), None),
+                [a, b, c, d] => (CubicBezier::new(a, b, c, d), Some(LOOKAHEAD)),
+                _ => return Err(JerryioError::BadSegment(i, points.len())),
+            };
+            // Keep facing the same way if the end point doesn't have a heading
+            heading = segment.controls.last().and_then(|control| control.heading).unwrap_or(heading);
+            auto.add_curves(vec![PathSegment {
+                curve,
+                max_speed: SpeedCurve::new(start_speed, speed),
+                end_heading: heading,
+                force_stanley: false,
+                pure_pursuit,
+                ..Default::default()
+            }]);
+        }
+        Ok(auto)
+    }
+
+    /// Read a path.jerryio export from `path` on the SD card, see
+    /// `Auto::from_jerryio`
+    #[allow(unused)]
+    pub fn load_jerryio(path: &str, name: Option<&str>) -> Result<Auto, JerryioError> {
+        let data = read_to_string(Path::new(path)).map_err(JerryioError::Io)?;
+        Auto::from_jerryio(&data, name)
+    }
+}
@@ -1,3 +1,14 @@
 pub mod auto;
+pub mod branch;
 pub mod chassis;
+pub mod exit;
+pub mod file;
+pub mod jerryio;
 pub mod path;
+pub mod profile;
+pub mod runner;
+pub mod sequence;
+pub mod trajectory;
+pub mod transform;
+pub mod trigger;
+pub mod validate;
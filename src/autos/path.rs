@@ -1,7 +1,14 @@
 use core::f64;
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::OnceLock};
 
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    autos::{chassis::Pid, exit::ExitCondition, profile::Constraints, transform::Transform},
+    util::dot,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub(crate) struct SpeedCurve {
     start_speed: f64,
     end_speed: f64,
@@ -13,6 +20,77 @@ impl SpeedCurve {
     pub fn sample(&self, t: f64) -> f64 { t * (self.end_speed - self.start_speed) + self.start_speed }
 }
 
+/// How far ahead of the robot pure pursuit picks its goal point \
+/// Fields: \
+///  `min: f64` - lookahead while stopped (in) \
+///  `max: f64` - longest the lookahead can get (in) \
+///  `speed_gain: f64` - extra lookahead per in/s of speed (s), 0.0 for a fixed
+/// lookahead
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Lookahead {
+    pub min: f64,
+    pub max: f64,
+    #[serde(default)]
+    pub speed_gain: f64 = 0.0,
+}
+
+#[allow(unused)]
+impl Lookahead {
+    /// Always look `distance` inches ahead
+    pub const fn fixed(distance: f64) -> Self { Self { min: distance, max: distance, .. } }
+
+    /// Look further ahead the faster the robot is going
+    pub const fn adaptive(min: f64, max: f64, speed_gain: f64) -> Self { Self { min, max, speed_gain } }
+}
+
+/// Gains for the RAMSETE controller, in meters like the PID gains \
+/// Fields: \
+///  `b: f64` - how hard to correct position error, larger is more aggressive \
+///  `zeta: f64` - damping of the correction, between 0.0 and 1.0
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Ramsete {
+    pub b: f64 = 2.0,
+    pub zeta: f64 = 0.7,
+}
+
+#[allow(unused)]
+impl Ramsete {
+    pub const fn new(b: f64, zeta: f64) -> Self { Self { b, zeta } }
+}
+
+/// Which side of the drivetrain stays still during a swing turn
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Side {
+    Left,
+    Right,
+}
+
+/// What a `Turn` should end up facing
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum TurnTarget {
+    Heading(f64),      // Heading in degrees
+    Point((f64, f64)), // Point to face, or face away from if the segment is reversed
+}
+
+/// A turn in place or swing turn, done instead of following the segment's
+/// curve \
+/// Fields: \
+///  `target: TurnTarget` - what to turn to \
+///  `swing: Option<Side>` - side to lock for a swing turn, `None` to turn in place \
+///  `pid: Option<Pid>` - angular PID (in radians) to use instead of the chassis' \
+///  `tolerance: f64` - exit once the heading is this close to the target (deg),
+/// chained turns use `end_heading_err` instead
+#[derive(Debug)]
+pub(crate) struct Turn {
+    pub target: TurnTarget,
+    pub swing: Option<Side> = None,
+    pub pid: Option<Pid> = None,
+    pub tolerance: f64 = 0.25,
+}
+
 pub(crate) trait Curve {
     fn sample(&self, t: f64) -> (f64, f64);
     fn sample_derivative(&self, t: f64) -> (f64, f64);
@@ -24,6 +102,111 @@ pub(crate) trait Curve {
     fn curve_type(&self) -> u8;
 
     fn dbg_fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
+
+    /// A copy of the curve moved with `transform`
+    fn transform(&self, transform: Transform) -> Box<dyn Curve>;
+
+    /// Table for converting between `t` and distance along the curve, built the
+    /// first time it's needed
+    fn arc_table(&self) -> &ArcLengthTable;
+
+    /// Total length of the curve (in)
+    fn length(&self) -> f64 { self.arc_table().length() }
+
+    /// Distance along the curve at `t` (in)
+    fn distance_at(&self, t: f64) -> f64 { self.arc_table().distance_at(t) }
+
+    /// Value of `t` once `distance` inches along the curve
+    #[allow(unused)]
+    fn t_at_distance(&self, distance: f64) -> f64 { self.arc_table().t_at(distance) }
+
+    /// Signed curvature at `t` (1/in), positive when the curve bends clockwise
+    /// like the heading
+    #[allow(unused)]
+    fn curvature(&self, t: f64) -> f64 {
+        let d = self.sample_derivative(t);
+        let dd = self.sample_derivative2(t);
+        let speed = d.0.hypot(d.1);
+        if speed < 1E-9 { 0.0 } else { (d.1 * dd.0 - d.0 * dd.1) / speed.powi(3) }
+    }
+
+    /// Value of `t` of the point on the curve closest to `pos`, refined from
+    /// `guess` with Newton's method \
+    /// Without a guess, the closest point in the arc length table is used as
+    /// the starting point
+    fn closest_point(&self, pos: (f64, f64), guess: Option<f64>) -> f64 {
+        let mut t = guess.unwrap_or_else(|| {
+            let dist = |t: f64| {
+                let p = self.sample(t);
+                (p.0 - pos.0).hypot(p.1 - pos.1)
+            };
+            (0..=ArcLengthTable::SAMPLES).map(|i| i as f64 / ArcLengthTable::SAMPLES as f64).min_by(|a, b| dist(*a).total_cmp(&dist(*b))).unwrap_or(0.0)
+        });
+        for _ in 0..5 {
+            let p = self.sample(t);
+            let dp = self.sample_derivative(t);
+            let ddp = self.sample_derivative2(t);
+
+            // Minimize the distance by finding where the vector between the robot and
+            // the curve is perpendicular to the curve
+            let path_to_robot = (p.0 - pos.0, p.1 - pos.1);
+            let f = dot(path_to_robot, dp);
+            let f_prime = dot(dp, dp) + dot(path_to_robot, ddp);
+
+            // If the derivative of our (kinda) error function is close to zero exit the
+            // loop as the benefits of continuing diminish rapidly
+            if f_prime.abs() < 1E-4 {
+                break;
+            }
+            t -= f / f_prime;
+        }
+        t
+    }
+}
+
+/// Distance along a `Curve` at evenly spaced values of `t`, so that `t` and
+/// distance can be converted between without integrating every update
+#[derive(Debug, Clone)]
+pub(crate) struct ArcLengthTable {
+    lengths: Vec<f64>,
+}
+
+#[allow(unused)]
+impl ArcLengthTable {
+    const SAMPLES: usize = 64;
+
+    /// Integrate the length of every section of `curve` with Simpson's rule
+    pub fn new(curve: &dyn Curve) -> Self {
+        let speed = |t: f64| {
+            let d = curve.sample_derivative(t);
+            d.0.hypot(d.1)
+        };
+        let h = 1.0 / Self::SAMPLES as f64;
+        let mut lengths = vec![0.0; Self::SAMPLES + 1];
+        for i in 0..Self::SAMPLES {
+            let t = i as f64 * h;
+            lengths[i + 1] = lengths[i] + h / 6.0 * (speed(t) + 4.0 * speed(t + h / 2.0) + speed(t + h));
+        }
+        Self { lengths }
+    }
+
+    pub fn length(&self) -> f64 { self.lengths[Self::SAMPLES] }
+
+    /// Distance at `t`, linearly interpolated between the samples
+    pub fn distance_at(&self, t: f64) -> f64 {
+        let x = t.clamp(0.0, 1.0) * Self::SAMPLES as f64;
+        let i = (x as usize).min(Self::SAMPLES - 1);
+        self.lengths[i] + (x - i as f64) * (self.lengths[i + 1] - self.lengths[i])
+    }
+
+    /// Value of `t` at `distance`, linearly interpolated between the samples
+    pub fn t_at(&self, distance: f64) -> f64 {
+        let distance = distance.clamp(0.0, self.length());
+        let i = self.lengths.partition_point(|l| *l < distance).clamp(1, Self::SAMPLES);
+        let section = self.lengths[i] - self.lengths[i - 1];
+        let frac = if section > 1E-9 { (distance - self.lengths[i - 1]) / section } else { 0.0 };
+        (i as f64 - 1.0 + frac) / Self::SAMPLES as f64
+    }
 }
 
 impl Debug for dyn Curve {
@@ -36,10 +219,11 @@ impl Debug for dyn Curve {
 pub(crate) struct LinearInterp {
     pub a: (f64, f64),
     pub b: (f64, f64),
+    pub lut: OnceLock<ArcLengthTable> = OnceLock::new(),
 }
 
 impl LinearInterp {
-    pub(crate) fn new(start: (f64, f64), end: (f64, f64)) -> Box<Self> { Box::new(Self { a: start, b: end }) }
+    pub(crate) fn new(start: (f64, f64), end: (f64, f64)) -> Box<Self> { Box::new(Self { a: start, b: end, .. }) }
 }
 
 impl Curve for LinearInterp {
@@ -52,6 +236,10 @@ impl Curve for LinearInterp {
     fn curve_type(&self) -> u8 { 0 }
 
     fn dbg_fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { self.fmt(f) }
+
+    fn transform(&self, transform: Transform) -> Box<dyn Curve> { LinearInterp::new(transform.point(self.a), transform.point(self.b)) }
+
+    fn arc_table(&self) -> &ArcLengthTable { self.lut.get_or_init(|| ArcLengthTable::new(self)) }
 }
 
 #[allow(unused)]
@@ -61,11 +249,12 @@ pub(crate) struct CubicBezier {
     pub b: (f64, f64),
     pub c: (f64, f64),
     pub d: (f64, f64),
+    pub lut: OnceLock<ArcLengthTable> = OnceLock::new(),
 }
 
 #[allow(unused)]
 impl CubicBezier {
-    pub(crate) fn new(start: (f64, f64), c1: (f64, f64), c2: (f64, f64), end: (f64, f64)) -> Box<Self> { Box::new(Self { a: start, b: c1, c: c2, d: end }) }
+    pub(crate) fn new(start: (f64, f64), c1: (f64, f64), c2: (f64, f64), end: (f64, f64)) -> Box<Self> { Box::new(Self { a: start, b: c1, c: c2, d: end, .. }) }
 }
 
 impl Curve for CubicBezier {
@@ -102,6 +291,12 @@ impl Curve for CubicBezier {
     fn curve_type(&self) -> u8 { 1 }
 
     fn dbg_fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { self.fmt(f) }
+
+    fn transform(&self, transform: Transform) -> Box<dyn Curve> {
+        CubicBezier::new(transform.point(self.a), transform.point(self.b), transform.point(self.c), transform.point(self.d))
+    }
+
+    fn arc_table(&self) -> &ArcLengthTable { self.lut.get_or_init(|| ArcLengthTable::new(self)) }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -110,6 +305,7 @@ pub(crate) struct CubicPolyBezier {
     pub b: (f64, f64) = (0.0, 0.0),
     pub c: (f64, f64) = (0.0, 0.0),
     pub d: (f64, f64) = (0.0, 0.0),
+    pub lut: OnceLock<ArcLengthTable> = OnceLock::new(),
 }
 
 impl Curve for CubicPolyBezier {
@@ -134,6 +330,13 @@ impl Curve for CubicPolyBezier {
     fn curve_type(&self) -> u8 { 2 }
 
     fn dbg_fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { self.fmt(f) }
+
+    // Every transform is linear, so the coefficients move the same way the points do
+    fn transform(&self, transform: Transform) -> Box<dyn Curve> {
+        Box::new(CubicPolyBezier { a: transform.point(self.a), b: transform.point(self.b), c: transform.point(self.c), d: transform.point(self.d), .. })
+    }
+
+    fn arc_table(&self) -> &ArcLengthTable { self.lut.get_or_init(|| ArcLengthTable::new(self)) }
 }
 
 #[derive(Debug)]
@@ -148,12 +351,19 @@ pub(crate) struct PathSegment {
     pub wait_time: f64,
     pub chained: bool,
     pub force_stanley: bool,
+    pub pure_pursuit: Option<Lookahead>,
+    pub ramsete: Option<Ramsete>,
+    pub boomerang: Option<f64>,
+    pub turn: Option<Turn>,
+    pub profile: Option<Constraints>,
+    pub exit: Option<ExitCondition>,
+    pub heading_exit: Option<ExitCondition>,
 }
 
 impl Default for PathSegment {
     fn default() -> Self {
         Self {
-            curve: Box::new(LinearInterp { a: (0.0, 0.0), b: (0.0, 0.0) }),
+            curve: LinearInterp::new((0.0, 0.0), (0.0, 0.0)),
             min_speed: SpeedCurve::new(0.0, 0.0),
             max_speed: SpeedCurve::new(1.0, 1.0),
             end_heading: 0.0,
@@ -163,6 +373,13 @@ impl Default for PathSegment {
             wait_time: 0.0,
             chained: false,
             force_stanley: true,
+            pure_pursuit: None,
+            ramsete: None,
+            boomerang: None,
+            turn: None,
+            profile: None,
+            exit: None,
+            heading_exit: None,
         }
     }
 }
@@ -226,6 +443,20 @@ impl PathSegment {
         self
     }
 
+    /// Stop driving to the end point once `condition` is met instead of when
+    /// the Chassis' linear PID settles or the robot drives past it
+    pub fn exit_when(&mut self, condition: ExitCondition) -> &mut PathSegment {
+        self.exit = Some(condition);
+        self
+    }
+
+    /// Stop turning to the end heading once `condition` is met instead of when
+    /// the Chassis' angular PID settles
+    pub fn heading_exit_when(&mut self, condition: ExitCondition) -> &mut PathSegment {
+        self.heading_exit = Some(condition);
+        self
+    }
+
     /// Set a wait time for after the `PathSegment` is finished being followed
     pub fn wait(&mut self, wait: f64) -> &mut PathSegment {
         self.wait_time = wait;
@@ -242,4 +473,50 @@ impl PathSegment {
         self.force_stanley = true;
         self
     }
+
+    /// Follow this `PathSegment` with pure pursuit instead of the PID or Stanley
+    /// controllers
+    pub fn pure_pursuit(&mut self, lookahead: Lookahead) -> &mut PathSegment {
+        self.pure_pursuit = Some(lookahead);
+        self
+    }
+
+    /// Use `pid` for this `PathSegment`'s turn instead of the chassis' angular PID
+    pub fn turn_pid(&mut self, pid: Pid) -> &mut PathSegment {
+        if let Some(turn) = &mut self.turn {
+            turn.pid = Some(pid);
+        }
+        self
+    }
+
+    /// Exit this `PathSegment`'s turn once the heading is within `tolerance`
+    /// degrees of the target
+    pub fn turn_tolerance(&mut self, tolerance: f64) -> &mut PathSegment {
+        if let Some(turn) = &mut self.turn {
+            turn.tolerance = tolerance;
+        }
+        self
+    }
+
+    /// Arc into the end heading instead of turning after arriving, by aiming at a
+    /// carrot point `lead` times the remaining distance back along the end heading \
+    /// Only used by the PID controller, `lead` is usually between 0.3 and 0.8
+    pub fn boomerang(&mut self, lead: f64) -> &mut PathSegment {
+        self.boomerang = Some(lead);
+        self
+    }
+
+    /// Track this `PathSegment` by time with RAMSETE, using its motion profile
+    /// (or a trapezoidal one at the segment's top speed) to time the path
+    pub fn ramsete(&mut self, gains: Ramsete) -> &mut PathSegment {
+        self.ramsete = Some(gains);
+        self
+    }
+
+    /// Follow a motion profile generated from `constraints` along the length of
+    /// the `PathSegment` instead of ramping the speed with slew
+    pub fn profile(&mut self, constraints: Constraints) -> &mut PathSegment {
+        self.profile = Some(constraints);
+        self
+    }
 }
@@ -0,0 +1,206 @@
+use core::f64;
+
+use serde::{Deserialize, Serialize};
+
+/// Limits used to generate a `MotionProfile` \
+/// Fields: \
+///  `max_vel: f64` - fastest the robot can go along the path (in/s) \
+///  `max_accel: f64` - fastest the robot can speed up or slow down (in/s^2) \
+///  `max_jerk: f64` - fastest the acceleration can change (in/s^3), infinite
+/// for a trapezoidal profile
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Constraints {
+    pub max_vel: f64,
+    pub max_accel: f64,
+    // JSON can't hold an infinite jerk, so trapezoidal profiles leave it out
+    #[serde(default = "infinite_jerk", skip_serializing_if = "is_infinite")]
+    pub max_jerk: f64 = f64::INFINITY,
+}
+
+fn infinite_jerk() -> f64 { f64::INFINITY }
+
+fn is_infinite(jerk: &f64) -> bool { jerk.is_infinite() }
+
+#[allow(unused)]
+impl Constraints {
+    /// Constant acceleration ramps, the acceleration jumps between 0 and
+    /// `max_accel`
+    pub const fn trapezoidal(max_vel: f64, max_accel: f64) -> Self { Self { max_vel, max_accel, .. } }
+
+    /// Jerk limited ramps, the acceleration ramps between 0 and `max_accel`
+    pub const fn s_curve(max_vel: f64, max_accel: f64, max_jerk: f64) -> Self { Self { max_vel, max_accel, max_jerk } }
+}
+
+/// Where the robot should be at a point in time along a `MotionProfile` \
+/// Fields: \
+///  `position: f64` - distance along the path (in) \
+///  `velocity: f64` - speed along the path (in/s) \
+///  `acceleration: f64` - acceleration along the path (in/s^2)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ProfileState {
+    pub position: f64,
+    pub velocity: f64,
+    pub acceleration: f64,
+}
+
+/// A velocity profile for driving `distance` inches, starting at `start_vel`
+/// and ending at `end_vel` \
+/// Uses the double S profile from Biagiotti & Melchiorri's "Trajectory
+/// Planning for Automatic Machines and Robots", which becomes a trapezoidal
+/// profile with an infinite jerk
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MotionProfile {
+    pub distance: f64,
+    pub start_vel: f64,
+    pub end_vel: f64,
+    jerk: f64,
+    // Length of the jerk phases while accelerating and decelerating (s)
+    t_j1: f64,
+    t_j2: f64,
+    // Length of the acceleration, cruise and deceleration phases (s)
+    t_a: f64,
+    t_v: f64,
+    t_d: f64,
+    // Acceleration and velocity actually reached
+    accel_lim: f64,
+    decel_lim: f64,
+    vel_lim: f64,
+}
+
+#[allow(unused)]
+impl MotionProfile {
+    pub fn new(distance: f64, start_vel: f64, end_vel: f64, constraints: Constraints) -> Self {
+        let distance = distance.max(0.0);
+        let jerk = constraints.max_jerk;
+        let max_vel = constraints.max_vel;
+        let start_vel = start_vel.clamp(0.0, max_vel);
+        let end_vel = end_vel.clamp(0.0, max_vel);
+        let mut max_accel = constraints.max_accel;
+        if distance < 1E-6 {
+            return Self { distance, start_vel, end_vel, jerk, t_j1: 0.0, t_j2: 0.0, t_a: 0.0, t_v: 0.0, t_d: 0.0, accel_lim: 0.0, decel_lim: 0.0, vel_lim: start_vel };
+        }
+
+        // Time spent in the jerk and acceleration phases to change speed by `dv`,
+        // along with the acceleration reached
+        let ramp = |dv: f64, accel: f64| {
+            if dv * jerk < accel * accel {
+                let t_j = (dv / jerk).sqrt();
+                (t_j, 2.0 * t_j, jerk * t_j)
+            } else {
+                let t_j = accel / jerk;
+                (t_j, t_j + dv / accel, accel)
+            }
+        };
+
+        // Assume that we reach the maximum velocity first
+        let (mut t_j1, mut t_a, mut accel_lim) = ramp(max_vel - start_vel, max_accel);
+        let (mut t_j2, mut t_d, mut decel_lim) = ramp(max_vel - end_vel, max_accel);
+        let mut t_v = distance / max_vel - t_a / 2.0 * (1.0 + start_vel / max_vel) - t_d / 2.0 * (1.0 + end_vel / max_vel);
+
+        // Otherwise there's no cruise phase, so shrink the acceleration until the ramps
+        // fit in the distance we have
+        if t_v <= 0.0 {
+            t_v = 0.0;
+            loop {
+                let t_j = max_accel / jerk;
+                let delta = max_accel.powi(4) / (jerk * jerk) + 2.0 * (start_vel * start_vel + end_vel * end_vel) + max_accel * (4.0 * distance - 2.0 * t_j * (start_vel + end_vel));
+                t_j1 = t_j;
+                t_j2 = t_j;
+                t_a = (max_accel * t_j - 2.0 * start_vel + delta.sqrt()) / (2.0 * max_accel);
+                t_d = (max_accel * t_j - 2.0 * end_vel + delta.sqrt()) / (2.0 * max_accel);
+                accel_lim = max_accel;
+                decel_lim = max_accel;
+
+                // Only enough room to speed up or slow down, not both
+                if t_a < 0.0 || t_d < 0.0 {
+                    let (from, to) = if t_a < 0.0 { (start_vel, end_vel) } else { (end_vel, start_vel) };
+                    let t_ramp = if from + to > 0.0 { 2.0 * distance / (from + to) } else { 0.0 };
+                    let t_j = if jerk.is_finite() && from + to > 0.0 {
+                        (jerk * distance - (jerk * (jerk * distance * distance + (from + to).powi(2) * (to - from))).max(0.0).sqrt()) / (jerk * (from + to))
+                    } else {
+                        0.0
+                    };
+                    let accel = if t_ramp - t_j > 0.0 { (from - to).abs() / (t_ramp - t_j) } else { 0.0 };
+                    if t_a < 0.0 {
+                        (t_a, t_j1, accel_lim, t_d, t_j2, decel_lim) = (0.0, 0.0, 0.0, t_ramp, t_j, accel);
+                    } else {
+                        (t_d, t_j2, decel_lim, t_a, t_j1, accel_lim) = (0.0, 0.0, 0.0, t_ramp, t_j, accel);
+                    }
+                    break;
+                }
+                // Both ramps need to be long enough to reach the acceleration
+                if t_a >= 2.0 * t_j && t_d >= 2.0 * t_j || max_accel < 1E-6 {
+                    break;
+                }
+                max_accel *= 0.99;
+            }
+        }
+
+        let vel_lim = if t_a > 0.0 { start_vel + (t_a - t_j1) * accel_lim } else if t_d > 0.0 { end_vel + (t_d - t_j2) * decel_lim } else { start_vel };
+        Self { distance, start_vel, end_vel, jerk, t_j1, t_j2, t_a, t_v, t_d, accel_lim, decel_lim, vel_lim }
+    }
+
+    /// How long the profile takes to follow (s)
+    pub fn duration(&self) -> f64 { self.t_a + self.t_v + self.t_d }
+
+    /// Fastest speed reached (in/s)
+    pub fn peak_velocity(&self) -> f64 { self.vel_lim }
+
+    /// Where the robot should be `t` seconds into the profile
+    pub fn sample(&self, t: f64) -> ProfileState {
+        let (t_a, t_d, t_j1, t_j2) = (self.t_a, self.t_d, self.t_j1, self.t_j2);
+        let (v0, v1, v_lim, j) = (self.start_vel, self.end_vel, self.vel_lim, self.jerk);
+        let total = self.duration();
+
+        if t <= 0.0 {
+            return ProfileState { position: 0.0, velocity: v0, acceleration: 0.0 };
+        }
+        if t >= total {
+            return ProfileState { position: self.distance, velocity: v1, acceleration: 0.0 };
+        }
+
+        // Phases with no length are skipped so the jerk is never multiplied by zero,
+        // which would give NaN for trapezoidal profiles
+        if t < t_j1 {
+            ProfileState { position: v0 * t + j * t.powi(3) / 6.0, velocity: v0 + j * t * t / 2.0, acceleration: j * t }
+        } else if t < t_a - t_j1 {
+            let a = self.accel_lim;
+            ProfileState { position: v0 * t + a / 6.0 * (3.0 * t * t - 3.0 * t_j1 * t + t_j1 * t_j1), velocity: v0 + a * (t - t_j1 / 2.0), acceleration: a }
+        } else if t < t_a {
+            let tr = t_a - t;
+            ProfileState { position: (v_lim + v0) * t_a / 2.0 - v_lim * tr + j * tr.powi(3) / 6.0, velocity: v_lim - j * tr * tr / 2.0, acceleration: j * tr }
+        } else if t < t_a + self.t_v {
+            ProfileState { position: (v_lim + v0) * t_a / 2.0 + v_lim * (t - t_a), velocity: v_lim, acceleration: 0.0 }
+        } else {
+            // Time since we started slowing down
+            let td = (t - total + t_d).max(0.0);
+            // Time left in the profile, used for the last phase so that rounding can't
+            // put us in it with no time left
+            let tr = total - t;
+            let start = self.distance - (v_lim + v1) * t_d / 2.0;
+            if td < t_j2 {
+                ProfileState { position: start + v_lim * td - j * td.powi(3) / 6.0, velocity: v_lim - j * td * td / 2.0, acceleration: -j * td }
+            } else if tr > t_j2 {
+                let a = self.decel_lim;
+                ProfileState {
+                    position: start + v_lim * td - a / 6.0 * (3.0 * td * td - 3.0 * t_j2 * td + t_j2 * t_j2),
+                    velocity: v_lim - a * (td - t_j2 / 2.0),
+                    acceleration: -a,
+                }
+            } else {
+                ProfileState { position: self.distance - v1 * tr - j * tr.powi(3) / 6.0, velocity: v1 + j * tr * tr / 2.0, acceleration: -j * tr }
+            }
+        }
+    }
+
+    /// Where the robot should be once it's driven `position` inches into the
+    /// profile, found by searching for the time it gets there
+    pub fn sample_distance(&self, position: f64) -> ProfileState {
+        let (mut low, mut high) = (0.0_f64, self.duration());
+        for _ in 0..50 {
+            let mid = low.midpoint(high);
+            if self.sample(mid).position < position { low = mid } else { high = mid }
+        }
+        self.sample(high)
+    }
+}
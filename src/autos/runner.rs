@@ -0,0 +1,133 @@
+use core::f64;
+
+use crate::autos::{
+    auto::{Action, Auto, SegmentExit},
+    chassis::Chassis,
+    trigger::Readings,
+};
+
+/// What the robot is doing with the current `PathSegment`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum MotionState {
+    #[default]
+    Driving, // Following the segment's curve to its end point
+    Turning(SegmentExit), // Got to the end point, how, and now turning to the end heading
+    Exited(SegmentExit),  // Done with the segment, the drivetrain stops on the next update
+    Waiting(SegmentExit), // Stopped, waiting out the segment's wait time
+}
+
+impl MotionState {
+    /// Has the robot stopped following the segment
+    pub fn is_done(&self) -> bool { matches!(self, MotionState::Exited(_) | MotionState::Waiting(_)) }
+}
+
+/// Something that moves the `MotionState` on, see `Auto::transition`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum MotionEvent {
+    Arrived(SegmentExit),  // Got to the end point of the curve, or drove past it
+    Finished(SegmentExit), // Done with the segment, usually by settling on the end heading, and how
+    TimedOut,              // The segment ran out of time
+    Stopped,               // The drivetrain was stopped after exiting
+    WaitOver,              // The wait after the segment is over
+}
+
+/// What one update of an `Auto` did \
+/// Fields: \
+///  `voltages: (f64, f64)` - left and right voltages to apply, as fractions of
+/// the maximum \
+///  `actions: Vec<Action>` - actions to run now \
+///  `exited: Option<(usize, SegmentExit)>` - segment that was exited during the
+/// update and why
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AutoStep {
+    pub voltages: (f64, f64),
+    pub actions: Vec<Action>,
+    pub exited: Option<(usize, SegmentExit)>,
+}
+
+impl Auto {
+    /// Move the `MotionState` on with `event`, events that don't apply to the
+    /// current state are ignored \
+    /// `Driving` -> `Turning` on `Arrived` \
+    /// `Driving` / `Turning` -> `Exited` on `Finished`, keeping how a `Turning`
+    /// segment arrived unless it finished some other way than settling \
+    /// `Driving` / `Turning` -> `Waiting` on `TimedOut` \
+    /// `Exited` -> `Waiting` on `Stopped` \
+    /// `Waiting` -> `Driving` the next segment on `WaitOver`, if there is one
+    pub fn transition(&mut self, event: MotionEvent) {
+        let next = match (self.state, event) {
+            (MotionState::Driving, MotionEvent::Arrived(exit)) => MotionState::Turning(exit),
+            (MotionState::Driving, MotionEvent::Finished(exit)) => MotionState::Exited(exit),
+            // Settling on the heading is the usual way to finish, anything else says more
+            // than how the robot got to the end point
+            (MotionState::Turning(exit), MotionEvent::Finished(SegmentExit::Settled)) => MotionState::Exited(exit),
+            (MotionState::Turning(_), MotionEvent::Finished(exit)) => MotionState::Exited(exit),
+            (MotionState::Driving | MotionState::Turning(_), MotionEvent::TimedOut) => MotionState::Waiting(SegmentExit::Timeout),
+            (MotionState::Exited(exit), MotionEvent::Stopped) => MotionState::Waiting(exit),
+            (MotionState::Waiting(_), MotionEvent::WaitOver) => {
+                if let Some(next) = self.next_segment() {
+                    self.start_segment(next);
+                }
+                return;
+            }
+            _ => return,
+        };
+        if let MotionState::Waiting(exit) = next {
+            // The wait is timed from when the robot stopped following the segment
+            self.motion_start = self.clock.now();
+            self.exits.push((self.current_curve, exit));
+        }
+        self.state = next;
+    }
+
+    /// Move on to driving `segment`
+    fn start_segment(&mut self, segment: usize) {
+        self.travelled += self.spline[self.current_curve].curve.length();
+        self.visited.resize(self.spline.len(), false);
+        self.visited[self.current_curve] = true;
+        self.visited[segment] = true;
+        self.current_curve = segment;
+        self.curve_t = 0.0;
+        self.segment_start = self.clock.now();
+        self.motion_start = self.clock.now();
+        self.state = MotionState::Driving;
+        self.close = false;
+        self.profile = None;
+        self.trajectory = None;
+        self.exit_timers.reset();
+    }
+
+    /// Step the auto forwards by one update, handling the timeout / exit / wait /
+    /// next segment transitions and returning the (left, right) voltages to apply
+    pub fn tick(&mut self, chassis: &mut Chassis) -> (f64, f64) {
+        let elapsed = self.clock.elapsed(self.motion_start).as_secs_f64() * 1000.0;
+        match self.state {
+            MotionState::Driving | MotionState::Turning(_) if elapsed >= self.get_timeout() => self.transition(MotionEvent::TimedOut),
+            MotionState::Exited(_) => self.transition(MotionEvent::Stopped),
+            MotionState::Waiting(_) => {
+                if elapsed >= self.get_wait() {
+                    self.transition(MotionEvent::WaitOver);
+                }
+            }
+            _ => return chassis.update(self),
+        }
+        (0.0, 0.0)
+    }
+
+    /// Run one update of the auto, driving with `chassis` and checking action
+    /// triggers with `readings`, for both the robot and the sim to use
+    pub fn step(&mut self, chassis: &mut Chassis, readings: &Readings) -> AutoStep {
+        let exits = self.exits.len();
+        // The exit conditions check the drive current
+        self.readings = *readings;
+        let voltages = self.tick(chassis);
+        let actions = self.poll_actions(readings);
+        AutoStep { voltages, actions, exited: self.exits.get(exits).copied() }
+    }
+
+    /// Has the last curve been exited and waited out
+    #[allow(unused)]
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, MotionState::Waiting(_)) && self.next_segment().is_none() && self.clock.elapsed(self.motion_start).as_secs_f64() * 1000.0 >= self.get_wait()
+    }
+}
@@ -0,0 +1,114 @@
+use core::f64;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::autos::{auto::Action, transform::Transform};
+
+/// One step of a `Sequence`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Step {
+    Run(Action),
+    Wait(f64), // Time before the next step (ms)
+}
+
+/// Actions spread out over time, run alongside the path once triggered instead
+/// of stopping the robot with a wait \
+/// Fields: \
+///  `steps: Vec<Step>` - actions and the waits between them, in order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Sequence {
+    pub steps: Vec<Step>,
+}
+
+#[allow(unused)]
+impl Sequence {
+    pub fn new(steps: Vec<Step>) -> Self { Self { steps } }
+
+    /// Run `action` for `duration` ms and then undo it
+    pub fn timed(action: Action, duration: f64) -> Self {
+        let mut steps = vec![Step::Run(action), Step::Wait(duration)];
+        steps.extend(action.undo().map(Step::Run));
+        Self { steps }
+    }
+
+    /// Run `action` for `on` ms and then undo it for `off` ms, `count` times
+    pub fn pulse(action: Action, count: usize, on: f64, off: f64) -> Self {
+        let mut steps = vec![];
+        for i in 0..count {
+            steps.extend(Self::timed(action, on).steps);
+            if i + 1 < count {
+                steps.push(Step::Wait(off));
+            }
+        }
+        Self { steps }
+    }
+
+    /// Follow this sequence with `other`
+    pub fn then(mut self, other: Sequence) -> Self {
+        self.steps.extend(other.steps);
+        self
+    }
+
+    /// Move the actions in the sequence with `transform`
+    pub fn transform(&mut self, transform: Transform) {
+        for step in &mut self.steps {
+            if let Step::Run(action) = step {
+                *action = transform.action(*action);
+            }
+        }
+    }
+}
+
+impl From<Action> for Sequence {
+    fn from(action: Action) -> Self { Self { steps: vec![Step::Run(action)] } }
+}
+
+/// A triggered `Sequence` that's partway through \
+/// Fields: \
+///  `sequence: usize` - index of the sequence in `Auto::sequences` \
+///  `step: usize` - next step to run \
+///  `step_start: Instant` - when the current wait started \
+///  `undo: Vec<Action>` - what to run to undo the actions that have run, if the
+/// sequence is cancelled
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RunningSequence {
+    pub sequence: usize,
+    pub step: usize,
+    pub step_start: Instant,
+    pub undo: Vec<Action>,
+}
+
+impl RunningSequence {
+    pub fn new(sequence: usize, now: Instant) -> Self { Self { sequence, step: 0, step_start: now, undo: vec![] } }
+
+    /// Run every step of `sequence` that's due at `now`, returning the actions
+    /// to run and whether the sequence is finished
+    pub fn advance(&mut self, sequence: &Sequence, now: Instant) -> (Vec<Action>, bool) {
+        let mut due = vec![];
+        while let Some(step) = sequence.steps.get(self.step) {
+            match *step {
+                Step::Run(action) => {
+                    // Running an action's undo means it doesn't need undoing any more
+                    if let Some(i) = self.undo.iter().position(|undo| *undo == action) {
+                        self.undo.remove(i);
+                    } else if let Some(undo) = action.undo().filter(|undo| !self.undo.contains(undo)) {
+                        self.undo.push(undo);
+                    }
+                    due.push(action);
+                }
+                Step::Wait(time) => {
+                    let end = self.step_start + Duration::from_secs_f64(time.max(0.0) / 1000.0);
+                    if now < end {
+                        break;
+                    }
+                    // Time the next wait from when this one should have ended so
+                    // that waits don't drift by an update each
+                    self.step_start = end;
+                }
+            }
+            self.step += 1;
+        }
+        (due, self.step >= sequence.steps.len())
+    }
+}
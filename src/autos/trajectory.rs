@@ -0,0 +1,89 @@
+use core::f64;
+
+use crate::autos::{path::Curve, profile::MotionProfile};
+
+/// Where the robot should be at a point in time along a `Trajectory` \
+/// Fields: \
+///  `time: f64` - time since the start of the trajectory (s) \
+///  `pose: (f64, f64, f64)` - position (in) and heading (rad) of the robot,
+/// facing backwards along the path when driving in reverse \
+///  `velocity: f64` - forward speed of the robot (in/s), negative in reverse \
+///  `acceleration: f64` - forward acceleration of the robot (in/s^2) \
+///  `angular_velocity: f64` - how fast the heading is changing (rad/s),
+/// positive clockwise like the heading
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct TrajectorySample {
+    pub time: f64,
+    pub pose: (f64, f64, f64),
+    pub velocity: f64,
+    pub acceleration: f64,
+    pub angular_velocity: f64,
+}
+
+/// A `Curve` timed by a `MotionProfile`, sampled every `Trajectory::DT`
+/// seconds so that it can be tracked by time instead of by position
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Trajectory {
+    pub samples: Vec<TrajectorySample>,
+}
+
+#[allow(unused)]
+impl Trajectory {
+    /// Time between samples (s)
+    pub const DT: f64 = 0.01;
+
+    pub fn new(curve: &dyn Curve, profile: &MotionProfile, reversed: bool) -> Self {
+        let duration = profile.duration();
+        let count = (duration / Self::DT).ceil() as usize;
+        let direction = if reversed { -1.0 } else { 1.0 };
+        let samples = (0..=count)
+            .map(|i| {
+                let time = (i as f64 * Self::DT).min(duration);
+                let state = profile.sample(time);
+                let t = curve.t_at_distance(state.position);
+                let pos = curve.sample(t);
+                let heading = (curve.sample_heading(t) + if reversed { f64::consts::PI } else { 0.0 }).rem_euclid(f64::consts::TAU);
+                // The heading turns by the path's curvature for every inch we drive along it,
+                // in the same direction whichever way we're facing
+                TrajectorySample {
+                    time,
+                    pose: (pos.0, pos.1, heading),
+                    velocity: state.velocity * direction,
+                    acceleration: state.acceleration * direction,
+                    angular_velocity: state.velocity * curve.curvature(t),
+                }
+            })
+            .collect();
+        Self { samples }
+    }
+
+    /// How long the trajectory takes to follow (s)
+    pub fn duration(&self) -> f64 { self.samples.last().map_or(0.0, |sample| sample.time) }
+
+    /// Where the robot should be `t` seconds into the trajectory, interpolated
+    /// between the two closest samples
+    pub fn sample(&self, t: f64) -> TrajectorySample {
+        let Some(last) = self.samples.last() else {
+            return TrajectorySample::default();
+        };
+        if t >= last.time {
+            return *last;
+        }
+        let i = ((t.max(0.0) / Self::DT) as usize).min(self.samples.len() - 2);
+        let (a, b) = (self.samples[i], self.samples[i + 1]);
+        let s = if b.time > a.time { ((t - a.time) / (b.time - a.time)).clamp(0.0, 1.0) } else { 0.0 };
+        let lerp = |a: f64, b: f64| a + (b - a) * s;
+        // Take the short way around when the heading wraps
+        let mut heading_diff = (b.pose.2 - a.pose.2).rem_euclid(f64::consts::TAU);
+        if heading_diff > f64::consts::PI {
+            heading_diff -= f64::consts::TAU;
+        }
+        TrajectorySample {
+            time: t,
+            pose: (lerp(a.pose.0, b.pose.0), lerp(a.pose.1, b.pose.1), (a.pose.2 + heading_diff * s).rem_euclid(f64::consts::TAU)),
+            velocity: lerp(a.velocity, b.velocity),
+            acceleration: lerp(a.acceleration, b.acceleration),
+            angular_velocity: lerp(a.angular_velocity, b.angular_velocity),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use core::f64;
+
+use crate::autos::{
+    auto::{Action, Auto},
+    path::{Side, TurnTarget},
+    trigger::{Condition, Trigger},
+};
+
+/// A way of moving an auto to another part of the field, about the centre of
+/// the field so that it can be undone by doing it again \
+/// Mirrors flip the robot's handedness, so swing turns and the left and right
+/// distance sensors swap sides, while whether a segment is driven in reverse
+/// stays the same for every transform
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Transform {
+    MirrorX,   // Mirror across the X axis, (x, y) -> (x, -y), for the other side of the field
+    MirrorY,   // Mirror across the Y axis, (x, y) -> (-x, y)
+    Rotate180, // Rotate half a turn, (x, y) -> (-x, -y), for the other alliance
+}
+
+impl Transform {
+    /// Where the point `p` (in) ends up
+    pub fn point(&self, p: (f64, f64)) -> (f64, f64) {
+        match self {
+            Transform::MirrorX => (p.0, -p.1),
+            Transform::MirrorY => (-p.0, p.1),
+            Transform::Rotate180 => (-p.0, -p.1),
+        }
+    }
+
+    /// Which way the heading `heading` (deg) ends up facing
+    pub fn heading(&self, heading: f64) -> f64 {
+        match self {
+            Transform::MirrorX => 180.0 - heading,
+            Transform::MirrorY => -heading,
+            Transform::Rotate180 => heading + 180.0,
+        }
+        .rem_euclid(360.0)
+    }
+
+    /// Does the transform flip left and right
+    pub fn is_mirror(&self) -> bool { *self != Transform::Rotate180 }
+
+    pub fn side(&self, side: Side) -> Side {
+        match (self.is_mirror(), side) {
+            (true, Side::Left) => Side::Right,
+            (true, Side::Right) => Side::Left,
+            (false, side) => side,
+        }
+    }
+
+    pub fn pose(&self, pose: (f64, f64, f64)) -> (f64, f64, f64) {
+        let p = self.point((pose.0, pose.1));
+        (p.0, p.1, self.heading(pose.2))
+    }
+
+    pub fn action(&self, action: Action) -> Action {
+        match action {
+            Action::ResetPose(x, y, theta) => {
+                let pose = self.pose((x, y, theta));
+                Action::ResetPose(pose.0, pose.1, pose.2)
+            }
+            Action::DistanceReset(sensor) => Action::DistanceReset(self.sensor(sensor)),
+            action => action,
+        }
+    }
+
+    pub fn trigger(&self, trigger: Trigger) -> Trigger {
+        match trigger {
+            Trigger::Condition(condition) => Trigger::Condition(self.condition(condition)),
+            trigger => trigger,
+        }
+    }
+
+    pub fn condition(&self, condition: Condition) -> Condition {
+        match condition {
+            Condition::DistanceBelow(sensor, distance) => Condition::DistanceBelow(self.sensor(sensor), distance),
+            condition => condition,
+        }
+    }
+
+    /// Which distance sensor sees what `sensor` did, the left and right sensors
+    /// are 0 and 1
+    fn sensor(&self, sensor: u8) -> u8 {
+        match sensor {
+            0 | 1 if self.is_mirror() => 1 - sensor,
+            sensor => sensor,
+        }
+    }
+}
+
+impl Auto {
+    /// Move the whole auto with `transform`, so that it only has to be written
+    /// for one side of the field or one alliance
+    pub fn transform(&mut self, transform: Transform) {
+        self.start_pose = transform.pose(self.start_pose);
+        for segment in &mut self.spline {
+            segment.curve = segment.curve.transform(transform);
+            segment.end_heading = transform.heading(segment.end_heading);
+            if let Some(turn) = &mut segment.turn {
+                turn.target = match turn.target {
+                    TurnTarget::Heading(heading) => TurnTarget::Heading(transform.heading(heading)),
+                    TurnTarget::Point(p) => TurnTarget::Point(transform.point(p)),
+                };
+                turn.swing = turn.swing.map(|side| transform.side(side));
+            }
+        }
+        for (action, trigger) in &mut self.actions {
+            *action = transform.action(*action);
+            *trigger = transform.trigger(*trigger);
+        }
+        for (sequence, trigger) in &mut self.sequences {
+            sequence.transform(transform);
+            *trigger = transform.trigger(*trigger);
+        }
+        for jump in &mut self.jumps {
+            jump.condition = jump.condition.map(|condition| transform.condition(condition));
+        }
+        self.reset_state();
+    }
+}
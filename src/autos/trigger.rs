@@ -0,0 +1,122 @@
+use core::f64;
+
+use serde::{Deserialize, Serialize};
+
+use crate::autos::auto::Auto;
+
+/// When an `Action` in an `Auto` runs \
+/// Every trigger runs its action once, on the first update where it's met,
+/// and triggers on the path are met as soon as the robot gets past them so
+/// that leaving a segment early can't skip them \
+/// Triggers on segments that a branch skips over are never met
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Trigger {
+    Position(f64),           // Segment index plus how far along that segment as a fraction of its length, whole numbers are the end of the segment before
+    Time(f64),               // Time since the auto started (ms)
+    SegmentTime(usize, f64), // Time since a segment started (ms), or when the auto moves on from it if that's sooner
+    Distance(f64),           // Distance driven along the path since the auto started (in)
+    SegmentStart(usize),     // When a segment starts
+    SegmentEnd(usize),       // When a segment exits, before its wait
+    Condition(Condition),    // As soon as a sensor condition is true
+}
+
+/// Something the robot's sensors can see or the time, checked every update
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Condition {
+    IntakeStalled,
+    DistanceBelow(u8, f64), // Distance sensor (0 left, 1 right, 2 front) sees something closer than this (in)
+    TimeAfter(f64),         // The auto has been running for longer than this (ms)
+}
+
+/// Sensor values for checking `Condition`s \
+/// Fields: \
+///  `intake_stalled: bool` - has either intake motor stalled \
+///  `distances: [Option<f64>; 3]` - left, right and front distance sensor
+/// readings (in), `None` if a sensor can't see anything \
+///  `drive_current: f64` - average current drawn by the drive motors (A), for
+/// the stall `ExitCondition`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Readings {
+    pub intake_stalled: bool = false,
+    pub distances: [Option<f64>; 3] = [None; 3],
+    pub drive_current: f64 = 0.0,
+}
+
+impl Condition {
+    /// Is the condition true with these sensor values, `elapsed` ms into the auto
+    pub fn is_met(&self, readings: &Readings, elapsed: f64) -> bool {
+        match *self {
+            Condition::IntakeStalled => readings.intake_stalled,
+            Condition::DistanceBelow(sensor, distance) => readings.distances.get(sensor as usize).copied().flatten().is_some_and(|reading| reading < distance),
+            Condition::TimeAfter(time) => elapsed > time,
+        }
+    }
+}
+
+impl Trigger {
+    /// Move a trigger written for part of an auto to where the part ends up,
+    /// `segments` segments and `distance` inches into the path \
+    /// Times since the start of the auto don't move, and the start of the part
+    /// becomes the start of its first segment so that it belongs to the part
+    pub fn offset(self, segments: usize, distance: f64) -> Trigger {
+        match self {
+            Trigger::Position(pos) if pos <= 0.0 => Trigger::SegmentStart(segments),
+            Trigger::Position(pos) => Trigger::Position(pos + segments as f64),
+            Trigger::SegmentTime(segment, time) => Trigger::SegmentTime(segment + segments, time),
+            Trigger::Distance(dist) => Trigger::Distance(dist + distance),
+            Trigger::SegmentStart(segment) => Trigger::SegmentStart(segment + segments),
+            Trigger::SegmentEnd(segment) => Trigger::SegmentEnd(segment + segments),
+            trigger => trigger,
+        }
+    }
+
+    /// Segment the trigger belongs to in a path of `len` segments, if it belongs
+    /// to one
+    pub fn segment(&self, len: usize) -> Option<usize> {
+        match *self {
+            // Whole numbers belong to the segment that ends there, so that skipping
+            // the segment after doesn't run them
+            Trigger::Position(pos) => Some(((pos.max(0.0).ceil() as usize).saturating_sub(1)).min(len.saturating_sub(1))),
+            Trigger::SegmentTime(segment, _) | Trigger::SegmentStart(segment) | Trigger::SegmentEnd(segment) => Some(segment),
+            _ => None,
+        }
+    }
+}
+
+impl Auto {
+    /// Length of the path up to the start of `segment` (in)
+    pub fn path_distance(&self, segment: usize) -> f64 { self.spline.iter().take(segment).map(|segment| segment.curve.length()).sum() }
+
+    /// Has the current segment been exited
+    fn segment_done(&self) -> bool { self.state.is_done() }
+
+    /// Has `segment` been driven and left behind
+    fn segment_passed(&self, segment: usize) -> bool { segment != self.current_curve && self.visited.get(segment).copied().unwrap_or(false) }
+
+    /// Is `condition` true right now
+    pub fn condition_met(&self, condition: Condition, readings: &Readings) -> bool {
+        condition.is_met(readings, self.clock.elapsed(self.auto_start).as_secs_f64() * 1000.0)
+    }
+
+    /// Has `trigger` been met yet
+    pub fn is_triggered(&self, trigger: Trigger, readings: &Readings) -> bool {
+        let elapsed = |start| self.clock.elapsed(start).as_secs_f64() * 1000.0;
+        // An exited segment counts as driven to the end, however it was exited
+        let progress = if self.segment_done() { 1.0 } else { self.progress() };
+        let on_segment = |segment: usize, f: &dyn Fn() -> bool| self.segment_passed(segment) || (self.current_curve == segment && f());
+        match trigger {
+            Trigger::Position(pos) => {
+                let segment = trigger.segment(self.spline.len()).unwrap_or(0);
+                on_segment(segment, &|| progress >= pos - segment as f64 - 1E-6)
+            }
+            Trigger::Time(time) => elapsed(self.auto_start) >= time,
+            Trigger::SegmentTime(segment, time) => on_segment(segment, &|| elapsed(self.segment_start) >= time || self.segment_done()),
+            Trigger::Distance(dist) => self.travelled + progress * self.spline[self.current_curve].curve.length() >= dist - 1E-6,
+            Trigger::SegmentStart(segment) => on_segment(segment, &|| true),
+            Trigger::SegmentEnd(segment) => on_segment(segment, &|| self.segment_done()),
+            Trigger::Condition(condition) => self.condition_met(condition, readings),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use core::f64;
+use std::fmt::{self, Display};
+
+use crate::{
+    autos::{
+        auto::{Auto, Autos},
+        trigger::Trigger,
+    },
+    comp::AutoHandler,
+    log_info, log_warn,
+};
+
+/// Furthest a point can be from the center of the field along either axis (in)
+const FIELD_HALF_WIDTH: f64 = 72.0;
+/// Furthest the first segment can start from the start pose (in)
+const START_TOLERANCE: f64 = 1.0;
+/// Shortest wait (ms) that does anything, one update of the auto loop, anything
+/// shorter was most likely meant in seconds
+const MIN_WAIT: f64 = 30.0;
+
+/// A mistake in an `Auto` that can be found without running it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Problem {
+    StartMismatch((f64, f64), (f64, f64)), // The start pose's position and where the first segment starts
+    TriggerPastEnd(usize, Trigger),        // Action whose trigger is past the end of the path, or on a segment that doesn't exist
+    SequencePastEnd(usize, Trigger),       // Same for a sequence
+    JumpPastEnd(usize),                    // Branch jump from or to a segment that doesn't exist
+    ActionOrder(usize, f64, f64),          // Action whose position is before the position of the action added before it
+    OffField(usize, (f64, f64)),           // Segment and a point on it that's outside the field
+    SpeedRange(usize, f64, f64),           // Segment whose minimum speed is over its maximum or outside [0, 1]
+    WaitUnits(usize, f64),                 // Segment whose wait (ms) is too short to be in milliseconds
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::StartMismatch(start, first) => write!(f, "start pose {start:?} isn't where the first segment starts, {first:?}"),
+            Problem::TriggerPastEnd(i, trigger) => write!(f, "action {i} is triggered by {trigger:?}, past the end of the path"),
+            Problem::SequencePastEnd(i, trigger) => write!(f, "sequence {i} is triggered by {trigger:?}, past the end of the path"),
+            Problem::JumpPastEnd(i) => write!(f, "jump {i} goes from or to a segment that doesn't exist"),
+            Problem::ActionOrder(i, pos, last) => write!(f, "action {i} is at {pos} but was added after an action at {last}"),
+            Problem::OffField(i, p) => write!(f, "segment {i} goes off the field at ({:.1}, {:.1})", p.0, p.1),
+            Problem::SpeedRange(i, min, max) => write!(f, "segment {i} has a minimum speed of {min} and a maximum of {max}"),
+            Problem::WaitUnits(i, wait) => write!(f, "segment {i} waits {wait} ms, less than a single update, is it in seconds?"),
+        }
+    }
+}
+
+impl Auto {
+    /// Everything wrong with the auto that can be found without running it
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+        let len = self.spline.len();
+
+        if let Some(first) = self.spline.first() {
+            let start = first.curve.sample(0.0);
+            if (start.0 - self.start_pose.0).hypot(start.1 - self.start_pose.1) > START_TOLERANCE {
+                problems.push(Problem::StartMismatch((self.start_pose.0, self.start_pose.1), start));
+            }
+        }
+
+        let length = self.path_distance(len);
+        let past_end = |trigger: &Trigger| match *trigger {
+            Trigger::Position(pos) => pos > len as f64,
+            Trigger::Distance(dist) => dist > length + 1E-6,
+            trigger => trigger.segment(usize::MAX).is_some_and(|segment| segment >= len),
+        };
+        for (i, (_, trigger)) in self.actions.iter().enumerate() {
+            if past_end(trigger) {
+                problems.push(Problem::TriggerPastEnd(i, *trigger));
+            }
+        }
+        for (i, (_, trigger)) in self.sequences.iter().enumerate() {
+            if past_end(trigger) {
+                problems.push(Problem::SequencePastEnd(i, *trigger));
+            }
+        }
+        for (i, jump) in self.jumps.iter().enumerate() {
+            if jump.from >= len || jump.to > len {
+                problems.push(Problem::JumpPastEnd(i));
+            }
+        }
+
+        // Actions are written in the order they happen, so going backwards is
+        // usually a typo in the position
+        let mut last = 0.0;
+        for (i, (_, trigger)) in self.actions.iter().enumerate() {
+            if let Trigger::Position(pos) = *trigger {
+                if pos < last {
+                    problems.push(Problem::ActionOrder(i, pos, last));
+                }
+                last = pos;
+            }
+        }
+
+        for (i, segment) in self.spline.iter().enumerate() {
+            let off_field = (0..=10).map(|t| segment.curve.sample(t as f64 / 10.0)).find(|p| p.0.abs() > FIELD_HALF_WIDTH || p.1.abs() > FIELD_HALF_WIDTH);
+            if let Some(p) = off_field {
+                problems.push(Problem::OffField(i, p));
+            }
+            for t in [0.0, 1.0] {
+                let (min, max) = (segment.min_speed.sample(t), segment.max_speed.sample(t));
+                if min > max || min < 0.0 || max <= 0.0 || max > 1.0 {
+                    problems.push(Problem::SpeedRange(i, min, max));
+                    break;
+                }
+            }
+        }
+
+        for (i, segment) in self.spline.iter().enumerate() {
+            if segment.wait_time > 0.0 && segment.wait_time < MIN_WAIT {
+                problems.push(Problem::WaitUnits(i, segment.wait_time));
+            }
+        }
+        problems
+    }
+}
+
+impl AutoHandler {
+    /// Check every auto for problems, logging them so they're found before the
+    /// auto gets selected
+    pub fn validate_autos(&self) -> Vec<(Autos, Problem)> {
+        let mut problems = vec![];
+        for (kind, auto) in &self.autos {
+            for problem in auto.validate() {
+                log_warn!("{kind:?}: {problem}");
+                problems.push((*kind, problem));
+            }
+        }
+        if problems.is_empty() {
+            log_info!("All {} autos are valid", self.autos.len());
+        }
+        problems
+    }
+}
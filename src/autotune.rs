@@ -0,0 +1,184 @@
+use core::f64;
+use std::time::Instant;
+
+use crate::{
+    autos::{auto::desaturate, chassis::PidGains},
+    clock::SharedClock,
+    log_info, log_warn,
+    util::dot,
+};
+
+/// Output of the relay while tuning, as a fraction of the maximum voltage
+const RELAY_OUTPUT: f64 = 0.3;
+/// How far the error has to cross zero before the relay switches, so that
+/// sensor noise can't flip it back and forth (radians for heading, meters for
+/// distance)
+const HYSTERESIS: (f64, f64) = (0.5 * f64::consts::PI / 180.0, 0.1 / 39.37);
+/// Cycles to let the oscillation settle into a steady rhythm before measuring it
+const SETTLE_CYCLES: usize = 1;
+/// Cycles to average the period and amplitude over
+const MEASURE_CYCLES: usize = 4;
+/// Longest a test can take before giving up on getting an oscillation (s)
+const TUNE_TIMEOUT: f64 = 10.0;
+/// Time between updates of the auto loop, which the `Pid` integrates and
+/// differentiates over (s)
+const AUTO_DT: f64 = 0.03;
+
+/// Which controller to tune
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Axis {
+    Heading,  // The angular PID, turning in place
+    Distance, // The linear PID, driving forwards and backwards
+}
+
+impl Axis {
+    fn hysteresis(self) -> f64 {
+        match self {
+            Axis::Heading => HYSTERESIS.0,
+            Axis::Distance => HYSTERESIS.1,
+        }
+    }
+}
+
+/// What a relay test measured and the gains suggested from it \
+/// Fields: \
+///  `ultimate_gain: f64` - proportional gain that would keep the robot
+/// oscillating \
+///  `ultimate_period: f64` - period of that oscillation (s) \
+///  `gains: PidGains` - suggested gains, in the units `Pid::update` uses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TuneResult {
+    pub ultimate_gain: f64,
+    pub ultimate_period: f64,
+    pub gains: PidGains,
+}
+
+impl TuneResult {
+    /// Ziegler-Nichols PD gains, the integral is left off since it's only for
+    /// the last bit of a motion and gets set by hand along with its band \
+    /// `Pid::update` differences the error once per update instead of over
+    /// time, so the derivative gain is scaled by the auto loop's update time
+    fn new(ultimate_gain: f64, ultimate_period: f64) -> Self {
+        let kp = 0.8 * ultimate_gain;
+        let kd = kp * ultimate_period / 8.0;
+        Self { ultimate_gain, ultimate_period, gains: PidGains { kp, ki: 0.0, kd: kd / AUTO_DT } }
+    }
+}
+
+/// Relay feedback test, switches the output between +/-`RELAY_OUTPUT` whenever
+/// the error crosses zero, making the robot oscillate around where it started
+/// at the frequency where its PID would go unstable \
+/// The amplitude and period of the oscillation give the ultimate gain and
+/// period for Ziegler-Nichols
+#[derive(Debug)]
+pub(crate) struct Autotune {
+    pub axis: Axis,
+    clock: SharedClock,
+    start: Instant,
+    start_pose: (f64, f64, f64),
+    output: f64,
+    last_switch: Option<Instant>,
+    // Smallest and largest error since the last switch
+    peaks: (f64, f64),
+    periods: Vec<f64>,
+    amplitudes: Vec<f64>,
+}
+
+impl Autotune {
+    /// Start tuning `axis` around `start_pose`, with the heading in radians like
+    /// `Tracking`
+    pub fn new(axis: Axis, start_pose: (f64, f64, f64), clock: SharedClock) -> Self {
+        log_info!("Tuning the {axis:?} PID");
+        Self {
+            axis,
+            start: clock.now(),
+            clock,
+            start_pose,
+            output: RELAY_OUTPUT,
+            last_switch: None,
+            peaks: (0.0, 0.0),
+            periods: vec![],
+            amplitudes: vec![],
+        }
+    }
+
+    /// Error the PID for `axis` would see at `pose`, in radians for heading and
+    /// meters for distance
+    fn error(&self, pose: (f64, f64, f64)) -> f64 {
+        match self.axis {
+            Axis::Heading => {
+                let mut err = (pose.2 - self.start_pose.2).rem_euclid(f64::consts::TAU);
+                if err > f64::consts::PI {
+                    err -= f64::consts::TAU;
+                }
+                err
+            }
+            Axis::Distance => {
+                let forward = (self.start_pose.2.sin(), self.start_pose.2.cos());
+                -dot((pose.0 - self.start_pose.0, pose.1 - self.start_pose.1), forward) / 39.37
+            }
+        }
+    }
+
+    /// Step the relay with the robot at `pose`, returning the (left, right)
+    /// voltages to apply, or `None` once the test is over
+    pub fn update(&mut self, pose: (f64, f64, f64)) -> Option<(f64, f64)> {
+        if self.is_done() {
+            return None;
+        }
+        if self.clock.elapsed(self.start).as_secs_f64() > TUNE_TIMEOUT {
+            log_warn!("The {:?} PID didn't oscillate, stopped tuning", self.axis);
+            return None;
+        }
+
+        let err = self.error(pose);
+        self.peaks = (self.peaks.0.min(err), self.peaks.1.max(err));
+        let hysteresis = self.axis.hysteresis();
+        let output = if err > hysteresis {
+            RELAY_OUTPUT
+        } else if err < -hysteresis {
+            -RELAY_OUTPUT
+        } else {
+            self.output
+        };
+        // A full cycle ends every time the relay switches back to positive
+        if output > 0.0 && self.output < 0.0 {
+            let now = self.clock.now();
+            if let Some(last) = self.last_switch {
+                self.periods.push(now.duration_since(last).as_secs_f64());
+                self.amplitudes.push((self.peaks.1 - self.peaks.0) / 2.0);
+            }
+            self.last_switch = Some(now);
+            self.peaks = (err, err);
+        }
+        self.output = output;
+
+        if self.is_done() {
+            return None;
+        }
+        // The same sign as the PIDs, a positive angular output turns
+        // counter-clockwise and a positive linear output drives forwards
+        Some(match self.axis {
+            Axis::Heading => desaturate((0.0, output)),
+            Axis::Distance => (output, output),
+        })
+    }
+
+    pub fn is_done(&self) -> bool { self.periods.len() >= SETTLE_CYCLES + MEASURE_CYCLES }
+
+    /// Gains from the oscillation, `None` if the test hasn't finished
+    pub fn result(&self) -> Option<TuneResult> {
+        if !self.is_done() {
+            return None;
+        }
+        let measured = SETTLE_CYCLES..;
+        let period = self.periods[measured.clone()].iter().sum::<f64>() / MEASURE_CYCLES as f64;
+        let amplitude = self.amplitudes[measured].iter().sum::<f64>() / MEASURE_CYCLES as f64;
+        // Describing function of a relay with hysteresis
+        let hysteresis = self.axis.hysteresis();
+        let ultimate_gain = 4.0 * RELAY_OUTPUT / (f64::consts::PI * (amplitude * amplitude - hysteresis * hysteresis).max(1E-12).sqrt());
+        let result = TuneResult::new(ultimate_gain, period);
+        log_info!("{:?} PID: Ku {ultimate_gain:.2}, Tu {period:.3} s, {:?}", self.axis, result.gains);
+        Some(result)
+    }
+}
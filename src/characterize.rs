@@ -0,0 +1,233 @@
+use core::f64;
+use std::{
+    fmt::{self, Display},
+    fs::{read_to_string, write},
+    io,
+    path::Path,
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    autos::chassis::Feedforward,
+    clock::SharedClock,
+    cubreg::{least_squares, r_squared},
+    log_info,
+};
+
+/// How fast the quasistatic tests ramp the voltage up (fraction of the maximum
+/// per second)
+const QUASISTATIC_RATE: f64 = 0.1;
+/// How long the quasistatic tests ramp for (s)
+const QUASISTATIC_TIME: f64 = 4.0;
+/// Voltage the dynamic tests step up to (fraction of the maximum)
+const DYNAMIC_VOLTAGE: f64 = 0.5;
+/// How long the dynamic tests hold the step for (s)
+const DYNAMIC_TIME: f64 = 1.5;
+/// How long to coast before each test so that the robot starts from a
+/// standstill (s)
+const REST_TIME: f64 = 1.5;
+/// Slowest a side can move and still be used in the feedforward fit, static
+/// friction hasn't been overcome below it (in/s)
+const MIN_VELOCITY: f64 = 1.0;
+/// Slowest the robot can turn and still be used in the track width fit (rad/s)
+const MIN_ANGULAR_VELOCITY: f64 = 0.2;
+
+/// How a test changes the voltage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Ramp {
+    Quasistatic, // Ramped up slowly, so the robot is barely accelerating and the voltage is all kS and kV
+    Dynamic,     // Stepped up at once, so the robot is mostly accelerating and kA shows up
+}
+
+/// Which way a test drives the robot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Movement {
+    Forward,
+    Backward,
+    TurnRight, // Clockwise in place
+    TurnLeft,  // Counter-clockwise in place
+}
+
+impl Movement {
+    /// Sign of the (left, right) voltages
+    fn sides(self) -> (f64, f64) {
+        match self {
+            Movement::Forward => (1.0, 1.0),
+            Movement::Backward => (-1.0, -1.0),
+            Movement::TurnRight => (1.0, -1.0),
+            Movement::TurnLeft => (-1.0, 1.0),
+        }
+    }
+
+    fn is_turn(self) -> bool { matches!(self, Movement::TurnRight | Movement::TurnLeft) }
+}
+
+/// The tests in the order they're run, alternating directions so that the
+/// robot ends up about where it started
+pub(crate) const TESTS: [(Ramp, Movement); 6] = [
+    (Ramp::Quasistatic, Movement::Forward),
+    (Ramp::Quasistatic, Movement::Backward),
+    (Ramp::Dynamic, Movement::Forward),
+    (Ramp::Dynamic, Movement::Backward),
+    (Ramp::Quasistatic, Movement::TurnRight),
+    (Ramp::Quasistatic, Movement::TurnLeft),
+];
+
+/// One update of a characterization test \
+/// Fields: \
+///  `test: usize` - index of the test in `TESTS` \
+///  `time: f64` - time since the test started (s) \
+///  `voltage: (f64, f64)` - (left, right) voltages applied, as fractions of the
+/// maximum \
+///  `velocity: (f64, f64)` - (left, right) wheel speeds (in/s) \
+///  `heading: Option<f64>` - IMU heading in radians clockwise like `Tracking`,
+/// `None` if the IMU isn't working
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Sample {
+    pub test: usize,
+    pub time: f64,
+    pub voltage: (f64, f64),
+    pub velocity: (f64, f64),
+    pub heading: Option<f64>,
+}
+
+/// Runs the `TESTS` on the Drivetrain one after another, recording a `Sample`
+/// every update for `fit` to find the feedforward gains and track width from
+#[derive(Debug)]
+pub(crate) struct Characterization {
+    clock: SharedClock,
+    test: usize,
+    test_start: Instant,
+    pub samples: Vec<Sample>,
+}
+
+impl Characterization {
+    pub fn new(clock: SharedClock) -> Self {
+        log_info!("Characterizing the Drivetrain, {} tests", TESTS.len());
+        Self { test_start: clock.now(), clock, test: 0, samples: vec![] }
+    }
+
+    /// Record the wheel speeds (in/s) and IMU heading (rad) and get the
+    /// (left, right) voltages to apply, `None` once every test has run
+    pub fn update(&mut self, velocity: (f64, f64), heading: Option<f64>) -> Option<(f64, f64)> {
+        let (ramp, movement) = *TESTS.get(self.test)?;
+        // Coast to a stop after the last test before starting this one
+        let time = self.clock.elapsed(self.test_start).as_secs_f64() - REST_TIME;
+        if time < 0.0 {
+            return Some((0.0, 0.0));
+        }
+        let (voltage, duration) = match ramp {
+            Ramp::Quasistatic => (QUASISTATIC_RATE * time, QUASISTATIC_TIME),
+            Ramp::Dynamic => (DYNAMIC_VOLTAGE, DYNAMIC_TIME),
+        };
+        if time > duration {
+            self.test += 1;
+            self.test_start = self.clock.now();
+            if self.test == TESTS.len() {
+                log_info!("Characterization done, {} samples", self.samples.len());
+                return None;
+            }
+            log_info!("Characterization test {}: {:?} {:?}", self.test, TESTS[self.test].0, TESTS[self.test].1);
+            return Some((0.0, 0.0));
+        }
+
+        let sides = movement.sides();
+        let voltage = (voltage * sides.0, voltage * sides.1);
+        self.samples.push(Sample { test: self.test, time, voltage, velocity, heading });
+        Some(voltage)
+    }
+
+    /// Write the samples to `path` as JSON, to be copied off the SD card and
+    /// fitted on a computer
+    pub fn save(&self, path: &Path) -> io::Result<()> { write(path, serde_json::to_string(&self.samples).map_err(io::Error::other)?) }
+}
+
+/// Read samples saved by `Characterization::save`
+#[allow(unused)]
+pub(crate) fn load_samples(path: &Path) -> io::Result<Vec<Sample>> { serde_json::from_str(&read_to_string(path)?).map_err(io::Error::other) }
+
+/// Gains found by `fit` \
+/// Fields: \
+///  `left: Feedforward` - left side feedforward \
+///  `right: Feedforward` - right side feedforward \
+///  `r_squared: (f64, f64)` - how well the (left, right) gains explain the
+/// voltages, 1 for a perfect fit \
+///  `track_width: Option<f64>` - effective track width (in), `None` if the IMU
+/// wasn't working during the turning tests
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Fit {
+    pub left: Feedforward,
+    pub right: Feedforward,
+    pub r_squared: (f64, f64),
+    pub track_width: Option<f64>,
+}
+
+impl Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |ff: &Feedforward| format!("{{ \"ks\": {:.6}, \"kv\": {:.6}, \"ka\": {:.6} }}", ff.ks, ff.kv, ff.ka);
+        writeln!(f, "r^2 left {:.4}, right {:.4}", self.r_squared.0, self.r_squared.1)?;
+        write!(f, "\"drivetrain\": {{ \"left\": {}, \"right\": {}", side(&self.left), side(&self.right))?;
+        match self.track_width {
+            Some(track_width) => write!(f, ", \"track_width\": {track_width:.3} }}"),
+            None => write!(f, " }}"),
+        }
+    }
+}
+
+/// Fit `voltage = kS * sign(velocity) + kV * velocity + kA * acceleration` to
+/// each side's straight line samples, and the track width to how fast the IMU
+/// says the robot turned for the difference in wheel speeds \
+/// Returns `None` if there aren't enough samples to fit the feedforward
+pub(crate) fn fit(samples: &[Sample]) -> Option<Fit> {
+    // Accelerations from the change in speed either side of each sample in the
+    // same test
+    let mut rows = (vec![], vec![]);
+    let mut voltages = (vec![], vec![]);
+    let mut turns = vec![];
+    for window in samples.windows(3) {
+        let [before, sample, after] = window else { continue };
+        if before.test != sample.test || after.test != sample.test {
+            continue;
+        }
+        let dt = after.time - before.time;
+        if dt <= 0.0 {
+            continue;
+        }
+        let Some((_, movement)) = TESTS.get(sample.test) else { continue };
+        if movement.is_turn() {
+            if let (Some(h0), Some(h1)) = (before.heading, after.heading) {
+                let mut turned = (h1 - h0).rem_euclid(f64::consts::TAU);
+                if turned > f64::consts::PI {
+                    turned -= f64::consts::TAU;
+                }
+                turns.push((turned / dt, sample.velocity.0 - sample.velocity.1));
+            }
+            continue;
+        }
+        let side = |velocity: f64, before: f64, after: f64| (velocity.abs() >= MIN_VELOCITY).then(|| vec![velocity.signum(), velocity, (after - before) / dt]);
+        if let Some(row) = side(sample.velocity.0, before.velocity.0, after.velocity.0) {
+            rows.0.push(row);
+            voltages.0.push(sample.voltage.0);
+        }
+        if let Some(row) = side(sample.velocity.1, before.velocity.1, after.velocity.1) {
+            rows.1.push(row);
+            voltages.1.push(sample.voltage.1);
+        }
+    }
+
+    let gains = |rows: &[Vec<f64>], voltages: &[f64]| {
+        let x = least_squares(rows, voltages)?;
+        Some((Feedforward { ks: x[0], kv: x[1], ka: x[2] }, r_squared(rows, voltages, &x)))
+    };
+    let (left, left_r2) = gains(&rows.0, &voltages.0)?;
+    let (right, right_r2) = gains(&rows.1, &voltages.1)?;
+
+    // Turning clockwise at w rad/s drives the left side w * track_width faster than
+    // the right
+    let turns: Vec<_> = turns.into_iter().filter(|(w, _)| w.abs() >= MIN_ANGULAR_VELOCITY).collect();
+    let track_width = (!turns.is_empty()).then(|| turns.iter().map(|(w, diff)| w * diff).sum::<f64>() / turns.iter().map(|(w, _)| w * w).sum::<f64>());
+
+    Some(Fit { left, right, r_squared: (left_r2, right_r2), track_width })
+}
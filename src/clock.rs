@@ -0,0 +1,52 @@
+use std::{
+    fmt::Debug,
+    sync::{Arc, nonpoison::RwLock},
+    time::{Duration, Instant},
+};
+
+/// Where the control code gets the time from \
+/// Use `RealClock` on the brain and `ManualClock` to step through time in tests
+pub(crate) trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Time since `earlier`, or zero if `earlier` is in the future
+    fn elapsed(&self, earlier: Instant) -> Duration { self.now().saturating_duration_since(earlier) }
+}
+
+/// A `Clock` that can be shared between everything that needs the time
+pub(crate) type SharedClock = Arc<dyn Clock>;
+
+/// The system clock
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant { Instant::now() }
+}
+
+impl RealClock {
+    pub fn shared() -> SharedClock { Arc::new(RealClock) }
+}
+
+/// A clock that only moves forwards when it's told to
+#[allow(unused)]
+#[derive(Debug)]
+pub(crate) struct ManualClock {
+    start: Instant,
+    offset: RwLock<Duration>,
+}
+
+#[allow(unused)]
+impl ManualClock {
+    pub fn new() -> Self { Self { start: Instant::now(), offset: RwLock::new(Duration::ZERO) } }
+
+    /// Move the clock forwards by `dt`
+    pub fn advance(&self, dt: Duration) { *self.offset.write() += dt; }
+
+    /// How far the clock has been moved since it was created
+    pub fn time(&self) -> Duration { *self.offset.read() }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant { self.start + *self.offset.read() }
+}
@@ -1,17 +1,18 @@
 use core::f64;
 use std::{
-    sync::{Arc, nonpoison::RwLock}, thread::sleep, time::{Duration, Instant}
+    path::{Path, PathBuf}, sync::{Arc, nonpoison::RwLock}, time::{Duration, Instant}
 };
 
 use crate::{
     autos::{
         auto::{Action, Auto, Autos},
-        path::{CubicPolyBezier, Curve, LinearInterp, PathSegment},
-    }, cubreg::curve_reg, log_debug, util::{dot, mag}
+        path::{Curve, LinearInterp, PathSegment, Ramsete},
+        profile::Constraints,
+    }, clock::{RealClock, SharedClock}, cubreg::curve_reg, log_info, log_warn, util::{dot, mag}
 };
 
-static MATCH_AUTO_TIME: f64 = Duration::from_secs(15).as_millis() as f64;
-static SKILLS_TIME: f64 = Duration::from_secs(60).as_millis() as f64;
+pub(crate) static MATCH_AUTO_TIME: f64 = Duration::from_secs(15).as_millis() as f64;
+pub(crate) static SKILLS_TIME: f64 = Duration::from_secs(60).as_millis() as f64;
 
 pub(crate) struct AutoHandler {
     pub autos: Vec<(Autos, Auto)>,
@@ -21,117 +22,252 @@ pub(crate) struct AutoHandler {
     pub start_recording: bool,
     pub recorded_poses: Vec<((f64, f64, f64), f64)>,
     pub recorded_actions: Vec<(Action, f64)>,
+    pub auto_dir: PathBuf,
+    pub clock: SharedClock,
 }
 
 impl AutoHandler {
     pub fn new() -> Self {
+        let clock = RealClock::shared();
         Self {
             autos: vec![],
-            start_time: Instant::now(),
+            start_time: clock.now(),
             selected_auto: Arc::new(RwLock::new(Autos::None)),
             is_recording: false,
             start_recording: false,
             recorded_poses: vec![],
             recorded_actions: vec![],
+            auto_dir: PathBuf::new(),
+            clock,
         }
     }
 
+    /// Use `clock` for the match timer and every auto instead of the system clock
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.autos.iter_mut().for_each(|(_, auto)| auto.set_clock(clock.clone()));
+        self.start_time = clock.now();
+        self.clock = clock;
+    }
+
+    /// Replace the compiled-in autos with any saved in `dir`, keeping the
+    /// compiled-in version of an auto if its file is missing or broken \
+    /// Recordings are saved to `dir` too
+    pub fn load_autos(&mut self, dir: &Path) {
+        self.auto_dir = dir.to_path_buf();
+        for kind in Autos::ALL {
+            let path = dir.join(kind.file_name());
+            if !path.exists() {
+                continue;
+            }
+            match Auto::load(&path) {
+                Ok(mut auto) => {
+                    log_info!("Loaded {kind:?} from {}", path.display());
+                    auto.set_clock(self.clock.clone());
+                    match self.autos.iter_mut().find(|(k, _)| *k == kind) {
+                        Some((_, existing)) => *existing = auto,
+                        None => self.autos.push((kind, auto)),
+                    }
+                }
+                Err(e) => log_warn!("Using the compiled-in {kind:?}, {e}"),
+            }
+        }
+    }
+
+    /// Time since the start of the current period
+    pub fn elapsed(&self) -> Duration { self.clock.elapsed(self.start_time) }
+
     pub fn get_auto(&mut self) -> &mut Auto { &mut self.autos.iter_mut().find(|a| a.0 == *self.selected_auto.read()).unwrap().1 }
 
     pub fn update(&mut self, _time_elapsed: Duration) {
-        let time = self.start_time.elapsed().as_millis() as f64;
-        let auto = *self.selected_auto.read();
-        if (auto == Autos::Skills && time > SKILLS_TIME) && (auto != Autos::None && auto != Autos::Skills && time > MATCH_AUTO_TIME) {
+        let time = self.elapsed().as_millis() as f64;
+        // Recordings are as long as the auto they're practice for
+        let limit = if *self.selected_auto.read() == Autos::Skills { SKILLS_TIME } else { MATCH_AUTO_TIME };
+        if self.is_recording && time > limit {
             self.is_recording = false;
-            self.process_recording();
+            self.save_recording();
         }
     }
 
-    fn process_recording(&mut self) {
-        let path_len = self.recorded_poses.len();
-        if path_len != 0 {
-            let mut search_ind = (0_usize, 1_usize);
-            let mut curve_out: Vec<PathSegment> = vec![];
-            let mut actions_out: Vec<(Action, f64)> = vec![];
-            let mut current_action = 0_usize;
-            loop {
-                let mut working_curve = CubicPolyBezier::default();
-                let mut last_heading_delta = 0.0;
-                let mut last_pose_delta = (0.0, 0.0);
-                loop {
-                    let mut heading_delta = (self.recorded_poses[search_ind.1].0.2 - self.recorded_poses[search_ind.1 - 1].0.2)
-                        / (self.recorded_poses[search_ind.1].1 - self.recorded_poses[search_ind.1 - 1].1);
-                    let pose_delta = ((self.recorded_poses[search_ind.1].0.0 - self.recorded_poses[search_ind.1 - 1].0.0)
-                        / (self.recorded_poses[search_ind.1].1 - self.recorded_poses[search_ind.1 - 1].1),
-                        (self.recorded_poses[search_ind.1].0.1 - self.recorded_poses[search_ind.1 - 1].0.1)
-                        / (self.recorded_poses[search_ind.1].1 - self.recorded_poses[search_ind.1 - 1].1));
-                    let pose_delta_2 = if search_ind.1 < 2 { (0.0, 0.0) } else {
-                        ((self.recorded_poses[search_ind.1 - 2].0.0 - self.recorded_poses[search_ind.1 - 1].0.0),
-                         (self.recorded_poses[search_ind.1 - 2].0.1 - self.recorded_poses[search_ind.1 - 1].0.1))
-                    };
-                    if dot(pose_delta, pose_delta_2) >= 0.0 { search_ind.1 -= 1; break; }
-                    if heading_delta > f64::consts::PI { heading_delta -= f64::consts::TAU; }
-                    last_heading_delta = heading_delta; last_pose_delta = pose_delta;
-                    if (self.recorded_poses[search_ind.1].1 - self.recorded_poses[search_ind.1 - 1].1) > 0.1 && mag(pose_delta) < 0.1 { search_ind.1 -= 1; break; }
-                    if mag(pose_delta) < 0.2 && heading_delta.abs() > 10.0_f64.to_radians() { search_ind.1 -= 1; break; }
-                    if heading_delta.abs() > 20.0_f64.to_radians() && search_ind.1 != 1 { search_ind.1 -= 1; break; }
-                    let x_vals: Vec<f64> = (search_ind.0..search_ind.1).map(|i| self.recorded_poses[i].0.0).collect();
-                    let y_vals: Vec<f64> = (search_ind.0..search_ind.1).map(|i| self.recorded_poses[i].0.1).collect();
-                    let t_vals: Vec<f64> = (search_ind.0..search_ind.1).map(|i| self.recorded_poses[i].1 / (self.recorded_poses[search_ind.1].1 - self.recorded_poses[search_ind.0].1)).collect();
-                    let curve = curve_reg(
-                        x_vals.clone(),
-                        y_vals.clone(),
-                        t_vals.clone()
-                    );
-                    let mad = t_vals.iter().enumerate().map(|t| {
-                        let curve_sample = curve.sample(*t.1);
-                        mag((curve_sample.0 - x_vals[t.0], curve_sample.1 - y_vals[t.0]))
-                    }).fold(0.0, |a, i| a + i);
-                    if mad > 0.25 { search_ind.1 -= 1; break; }
-                    working_curve = curve;
-                    search_ind.1 += 1;
-                    if search_ind.1 == path_len { search_ind.1 -= 1; break; }
-                }
-                loop {
-                    if self.recorded_actions[current_action].1 < self.recorded_poses[search_ind.1].1 {
-                        actions_out.push((self.recorded_actions[current_action].0, self.recorded_actions[current_action].1 / (self.recorded_poses[search_ind.1].1 - self.recorded_poses[search_ind.0].1) + curve_out.len() as f64));
-                        current_action += 1;
-                    } else { break; }
+    /// Use `auto` from now on, as long as there is one of that type
+    pub fn select(&mut self, auto: Autos) {
+        if *self.selected_auto.read() == auto {
+            return;
+        }
+        if self.autos.iter().any(|(kind, _)| *kind == auto) {
+            log_info!("Selected {auto:?}");
+            *self.selected_auto.write() = auto;
+        } else {
+            log_warn!("There's no {auto:?} auto, keeping {:?}", *self.selected_auto.read());
+        }
+    }
+
+    /// Note down `action` while recording, spinning and stopping mechanisms only
+    /// counts when it changes what the mechanism was doing
+    pub fn record_action(&mut self, action: Action) {
+        if !self.is_recording {
+            return;
+        }
+        let mechanism = |action: &Action| match action {
+            Action::SpinIntake(_) | Action::StopIntake => Some(0),
+            Action::SpinIndexer(_) | Action::StopIndexer => Some(1),
+            _ => None,
+        };
+        if let Some(device) = mechanism(&action) {
+            let last = self.recorded_actions.iter().rev().find(|(recorded, _)| mechanism(recorded) == Some(device));
+            if last.is_some_and(|(recorded, _)| *recorded == action) {
+                return;
+            }
+        }
+        self.recorded_actions.push((action, self.elapsed().as_millis() as f64));
+    }
+
+    /// Turn the recording into the `Recorded` auto and save it next to the
+    /// other autos so that it's still there after a reboot
+    pub fn save_recording(&mut self) {
+        let Some(mut auto) = self.process_recording() else {
+            log_warn!("Nothing was recorded");
+            return;
+        };
+        log_info!("Recorded {} segments and {} actions", auto.spline.len(), auto.actions.len());
+        for problem in auto.validate() {
+            log_warn!("Recorded: {problem}");
+        }
+        let path = self.auto_dir.join(Autos::Recorded.file_name());
+        match auto.save(&path) {
+            Ok(()) => log_info!("Saved the recording to {}", path.display()),
+            Err(e) => log_warn!("Couldn't save the recording, {e}"),
+        }
+        auto.set_clock(self.clock.clone());
+        match self.autos.iter_mut().find(|(kind, _)| *kind == Autos::Recorded) {
+            Some((_, existing)) => *existing = auto,
+            None => self.autos.push((Autos::Recorded, auto)),
+        }
+    }
+
+    /// Fit an auto to the recorded poses and actions \
+    /// The recording is split wherever the robot stops or changes direction,
+    /// each piece driven becomes a `CubicPolyBezier` fitted with `curve_reg`, and
+    /// each stop becomes a turn if the robot turned in place or a wait if not \
+    /// Actions are placed along the segment that was being driven when they
+    /// were recorded
+    pub fn process_recording(&self) -> Option<Auto> {
+        let poses = &self.recorded_poses;
+        if poses.len() < 2 {
+            return None;
+        }
+
+        // Split the recording into runs of updates where the robot was stopped,
+        // driving forwards or driving backwards
+        let motion = |i: usize| {
+            let ((x0, y0, _), t0) = poses[i - 1];
+            let ((x1, y1, h1), t1) = poses[i];
+            let delta = (x1 - x0, y1 - y0);
+            if mag(delta) / (t1 - t0).max(1.0) * 1000.0 < RECORDING_STOP_SPEED {
+                Motion::Stopped
+            } else if dot(delta, (h1.sin(), h1.cos())) < 0.0 {
+                Motion::Reversing
+            } else {
+                Motion::Driving
+            }
+        };
+        let mut pieces: Vec<(Motion, usize, usize)> = vec![];
+        for i in 1..poses.len() {
+            let motion = motion(i);
+            match pieces.last_mut() {
+                Some((last, _, end)) if *last == motion => *end = i,
+                _ => pieces.push((motion, i - 1, i)),
+            }
+        }
+
+        let mut auto = Auto::new();
+        let start = poses[0].0;
+        auto.start_pose = (start.0, start.1, start.2.to_degrees().rem_euclid(360.0));
+        // When each segment started and ended in the recording (ms)
+        let mut spans = vec![];
+        for (motion, first, last) in pieces {
+            let ((x, y, heading), t1) = poses[last];
+            let t0 = poses[first].1;
+            let heading = heading.to_degrees().rem_euclid(360.0);
+            match motion {
+                Motion::Stopped => {
+                    let mut turned = (heading - auto.end_pose().2).rem_euclid(360.0);
+                    if turned > 180.0 {
+                        turned -= 360.0;
+                    }
+                    if turned.abs() > RECORDING_TURN {
+                        auto.turn_to_heading(heading).timeout(recording_timeout(t1 - t0));
+                    } else {
+                        auto.wait_for(t1 - t0);
+                    }
                 }
-                curve_out.push(PathSegment {
-                    curve: Box::new(working_curve),
-                    end_heading: self.recorded_poses[search_ind.1].0.2,
-                    end_heading_err: 5.0,
-                    reversed_drive: dot(((self.recorded_poses[search_ind.1].0.0 - self.recorded_poses[search_ind.0].0.0),
-                                             (self.recorded_poses[search_ind.1].0.1 - self.recorded_poses[search_ind.0].0.1)),
-                                        (self.recorded_poses[search_ind.1].0.2.cos(), self.recorded_poses[search_ind.1].0.2.sin())) <= 0.0,
-                    timeout: self.recorded_poses[search_ind.1].1 - self.recorded_poses[search_ind.0].1,
-                    wait_time: if search_ind.1 == path_len - 1 { 0.0 } else { 1000.0 * (self.recorded_poses[search_ind.1 + 1].1 - self.recorded_poses[search_ind.1].1) },
-                    chained: true,
-                    force_stanley: true,
-                    ..Default::default()
-                });
-                if mag(last_pose_delta) < 0.2 && last_heading_delta.abs() > 10.0_f64.to_radians() {
-                    curve_out.push(PathSegment {
-                        curve: LinearInterp::new(
-                            (self.recorded_poses[search_ind.1 + 1].0.0, self.recorded_poses[search_ind.1 + 1].0.1),
-                            (self.recorded_poses[search_ind.1 + 1].0.0, self.recorded_poses[search_ind.1 + 1].0.1)),
-                        end_heading: self.recorded_poses[search_ind.1 + 1].0.2,
+                Motion::Driving | Motion::Reversing => {
+                    // Drive it as fast as the driver did, slowing down for the end
+                    let speed = (first + 1..=last).map(|i| mag((poses[i].0.0 - poses[i - 1].0.0, poses[i].0.1 - poses[i - 1].0.1)) / (poses[i].1 - poses[i - 1].1).max(1.0) * 1000.0).fold(0.0, f64::max);
+                    let end = auto.end_pose();
+                    auto.add_curves(vec![PathSegment {
+                        curve: fit_recording(&poses[first..=last], (end.0, end.1), (x, y)),
+                        end_heading: heading,
                         end_heading_err: 5.0,
-                        chained: true,
+                        reversed_drive: motion == Motion::Reversing,
+                        timeout: recording_timeout(t1 - t0),
+                        // Pieces end where the robot stopped or turned around, so
+                        // there's nothing to chain into
+                        ramsete: Some(Ramsete { .. }),
+                        profile: Some(Constraints::trapezoidal(speed, speed * 2.0)),
                         ..Default::default()
-                    });
-                }
-                search_ind.0 = search_ind.1;
-                search_ind.1 = search_ind.0 + 1;
-                if search_ind.0 == path_len - 1 {
-                    let mut export = Auto::new();
-                    export.add_curves(curve_out);
-                    export.add_actions(actions_out);
-                    export.spline.iter().for_each(|c| { log_debug!("{:?}", c.curve); sleep(Duration::from_millis(2)); });
-                    break;
+                    }]);
                 }
             }
+            spans.push((t0, t1));
+        }
+
+        for &(action, time) in &self.recorded_actions {
+            let segment = spans.iter().position(|(_, end)| time < *end).unwrap_or(spans.len() - 1);
+            let (t0, t1) = spans[segment];
+            let along = ((time - t0) / (t1 - t0).max(1.0)).clamp(0.0, 1.0);
+            auto.add_action(action, segment as f64 + along);
         }
+        Some(auto)
+    }
+}
+
+/// Slowest the robot can move in a recording and still be driving (in/s)
+const RECORDING_STOP_SPEED: f64 = 2.0;
+/// Smallest turn in place in a recording that becomes a turn instead of a wait (deg)
+const RECORDING_TURN: f64 = 5.0;
+/// Extra time a recorded segment gets on top of twice how long the driver took (ms)
+const RECORDING_TIMEOUT: f64 = 500.0;
+
+/// Timeout for a recorded segment that took the driver `duration` ms, the
+/// driver can speed up and turn faster than the controllers do
+fn recording_timeout(duration: f64) -> f64 { duration * 2.0 + RECORDING_TIMEOUT }
+
+/// What the robot was doing between two recorded poses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Stopped,
+    Driving,
+    Reversing,
+}
+
+/// Fit a curve to a run of recorded poses from `from` to `to`, the poses'
+/// times are spread over `t` from 0 to 1 \
+/// Too few poses to fit a cubic to get a straight line instead
+fn fit_recording(poses: &[((f64, f64, f64), f64)], from: (f64, f64), to: (f64, f64)) -> Box<dyn Curve> {
+    if poses.len() < 4 {
+        return LinearInterp::new(from, to);
     }
+    let (t0, t1) = (poses[0].1, poses[poses.len() - 1].1);
+    let mut curve = curve_reg(
+        poses.iter().map(|pose| pose.0.0).collect(),
+        poses.iter().map(|pose| pose.0.1).collect(),
+        poses.iter().map(|pose| (pose.1 - t0) / (t1 - t0)).collect(),
+    );
+    // Move the ends onto the path so far and the last pose, keeping the shape
+    // in between, so that the next segment carries on from the right place
+    let (start, end) = (curve.sample(0.0), curve.sample(1.0));
+    let (d_start, d_end) = ((from.0 - start.0, from.1 - start.1), (to.0 - end.0, to.1 - end.1));
+    curve.c = (curve.c.0 + d_end.0 - d_start.0, curve.c.1 + d_end.1 - d_start.1);
+    curve.d = (curve.d.0 + d_start.0, curve.d.1 + d_start.1);
+    Box::new(curve)
 }
@@ -8,7 +8,10 @@ use std::{
 use serde::{Deserialize, Serialize};
 use serde_json::{from_str, to_string};
 
-use crate::log_warn;
+use crate::{
+    autos::chassis::{Feedforward, PidGains},
+    log_warn,
+};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub(crate) struct ControllerConfig {
@@ -19,12 +22,49 @@ pub(crate) struct ControllerConfig {
     pub curve_amt: f64,
 }
 
+/// Measured constants of the drivetrain, from the characterization in
+/// `characterize` \
+/// Fields: \
+///  `left: Feedforward` - left side feedforward \
+///  `right: Feedforward` - right side feedforward \
+///  `track_width: f64` - effective distance between the left and right wheels
+/// (in), wider than the real one since the wheels scrub when turning
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DrivetrainConfig {
+    pub left: Feedforward,
+    pub right: Feedforward,
+    pub track_width: f64,
+}
+
+impl Default for DrivetrainConfig {
+    fn default() -> Self { Self { left: Feedforward::DEFAULT, right: Feedforward::DEFAULT, track_width: 10.37 } }
+}
+
+/// Gains for the Chassis' PIDs, the rest of their settings stay in the code
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PidConfig {
+    pub linear: PidGains,
+    pub angular: PidGains,
+}
+
+impl Default for PidConfig {
+    fn default() -> Self {
+        let gains = PidGains { kp: 8.0, ki: 0.0, kd: 20.0 };
+        Self { linear: gains, angular: gains }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub(crate) struct Config {
     pub ports: [u8; 9],
     pub names: [String; 9],
     pub reversed: [bool; 9],
     pub controller: ControllerConfig,
+    // Config files from before the drivetrain was characterized keep working
+    #[serde(default)]
+    pub drivetrain: DrivetrainConfig,
+    #[serde(default)]
+    pub pid: PidConfig,
 }
 
 const DEFAULT_JSON: &str = "{
@@ -37,6 +77,15 @@ const DEFAULT_JSON: &str = "{
         \"right_deadzone_inner\": 0.01,
         \"right_deadzone_outer\": 1.0,
         \"curve_amt\": 0.1028
+    },
+    \"drivetrain\": {
+        \"left\":  { \"ks\": 0.0, \"kv\": 0.013055, \"ka\": 0.002353 },
+        \"right\": { \"ks\": 0.0, \"kv\": 0.013055, \"ka\": 0.002353 },
+        \"track_width\": 10.37
+    },
+    \"pid\": {
+        \"linear\":  { \"kp\": 8.0, \"ki\": 0.0, \"kd\": 20.0 },
+        \"angular\": { \"kp\": 8.0, \"ki\": 0.0, \"kd\": 20.0 }
     }
 }";
 
@@ -56,7 +105,7 @@ impl Config {
         from_str::<Config>(file.as_str()).unwrap_or(from_str::<Config>(DEFAULT_JSON).expect("Incorrect Default JSON"))
     }
 
-    pub fn _save(&mut self) {
+    pub fn save(&self) {
         match write(Path::new("conf.json"), to_string(&self).unwrap()) {
             Ok(_) => (),
             Err(e) => {
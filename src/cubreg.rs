@@ -1,4 +1,4 @@
-use nalgebra::{Matrix4, Vector4};
+use nalgebra::{DMatrix, DVector, Matrix4, Vector4};
 
 use crate::autos::path::CubicPolyBezier;
 
@@ -45,5 +45,25 @@ pub(crate) fn curve_reg(x_values: Vec<f64>, y_values: Vec<f64>, t_values: Vec<f6
         b: (x[1], y[1]),
         c: (x[2], y[2]),
         d: (x[3], y[3]),
+        ..
     }
 }
+
+/// Least squares fit of `y = rows * x`, one row of inputs per value in `y` \
+/// Returns `None` if the inputs don't pin every coefficient down
+pub(crate) fn least_squares(rows: &[Vec<f64>], y: &[f64]) -> Option<Vec<f64>> {
+    let cols = rows.first()?.len();
+    let a = DMatrix::from_fn(rows.len(), cols, |i, j| rows[i][j]);
+    let b = DVector::from_column_slice(y);
+    let a_t = a.transpose();
+    (&a_t * &a).lu().solve(&(a_t * b)).map(|x| x.iter().copied().collect())
+}
+
+/// Fraction of the variance in `y` that `rows * x` explains, 1 for a perfect
+/// fit
+pub(crate) fn r_squared(rows: &[Vec<f64>], y: &[f64], x: &[f64]) -> f64 {
+    let mean = y.iter().sum::<f64>() / y.len() as f64;
+    let residual: f64 = rows.iter().zip(y).map(|(row, y)| (y - row.iter().zip(x).map(|(a, x)| a * x).sum::<f64>()).powi(2)).sum();
+    let total: f64 = y.iter().map(|y| (y - mean).powi(2)).sum();
+    if total == 0.0 { 1.0 } else { 1.0 - residual / total }
+}
@@ -9,6 +9,7 @@ use vexide::{battery, color::Color, display::*, math::Point2, time::sleep};
 
 use crate::{
     autos::auto::Autos,
+    autotune::{Axis, TuneResult},
     telemetry::{MotorType, Telem},
 };
 
@@ -48,6 +49,7 @@ enum GuiState {
     SensorView,
     AutoSelectorOverview,
     AutoSelectorMatch,
+    TuneView,
     // Right Side Views
     ControlsView,
     OdomCalibrateView,
@@ -132,11 +134,13 @@ fn draw_auto_overview(disp: &mut Display) {
     draw_rounded_rect(disp, (9, 8), (234, 119), 6, colors::RED);
     draw_rounded_rect(disp, (9, 121), (120, 232), 6, colors::GREEN);
     draw_rounded_rect(disp, (123, 121), (234, 232), 6, colors::BLUE);
-    draw_rounded_rect(disp, (123, 121), (234, 232), 6, colors::PURPLE);
+    draw_rounded_rect(disp, (123, 121), (234, 175), 6, colors::PURPLE);
+    draw_rounded_rect(disp, (123, 177), (234, 232), 6, colors::ORANGE);
     draw_text_center(disp, "Match", [52, 43], sizes::MEDIUM, colors::TEXT_2, colors::RED);
     draw_text_center(disp, "Elims", [52, 43], sizes::MEDIUM, colors::TEXT_2, colors::GREEN);
     draw_text_center(disp, "Skills", [39, 138], sizes::MEDIUM, colors::TEXT_2, colors::BLUE);
-    draw_text_center(disp, "None", [164, 150], sizes::MEDIUM, colors::TEXT_2, colors::PURPLE);
+    draw_text_center(disp, "None", [178, 148], sizes::MEDIUM, colors::TEXT_2, colors::PURPLE);
+    draw_text_center(disp, "Recorded", [178, 204], sizes::MEDIUM, colors::TEXT_2, colors::ORANGE);
 }
 
 fn draw_auto_selector_match(disp: &mut Display) {
@@ -149,6 +153,25 @@ fn draw_auto_selector_match(disp: &mut Display) {
     draw_text(disp, "Right", [83, 184], sizes::MEDIUM, colors::TEXT_1, colors::BLUE);
 }
 
+fn draw_tune_button(disp: &mut Display, name: &str, tuning: bool, result: Option<TuneResult>, y: i16, color: Color) {
+    draw_rounded_rect(disp, (9, y), (234, y + 56), 6, color);
+    draw_text_center(disp, if tuning { "Tuning, A stops" } else { name }, [121, y + 18], sizes::MEDIUM, colors::TEXT_1, color);
+    if let Some(result) = result {
+        let gains = result.gains;
+        draw_text_center(disp, &format!("kP {:.2} kI {:.2} kD {:.2}", gains.kp, gains.ki, gains.kd), [121, y + 40], sizes::SMALL, colors::TEXT_1, color);
+    }
+}
+
+fn draw_tune_menu(disp: &mut Display, telem: &Telem) {
+    draw_rounded_rect(disp, (6, 6), (237, 234), 6, colors::BG_2);
+    draw_tune_button(disp, "Tune Heading", telem.tuning == Some(Axis::Heading), telem.tune_results.0, 8, colors::BLUE);
+    draw_tune_button(disp, "Tune Distance", telem.tuning == Some(Axis::Distance), telem.tune_results.1, 66, colors::PURPLE);
+    draw_rounded_rect(disp, (9, 124), (234, 176), 6, colors::GREEN);
+    draw_rounded_rect(disp, (9, 178), (234, 232), 6, colors::BG_3);
+    draw_text_center(disp, "Save to Config", [121, 150], sizes::MEDIUM, colors::BG_1, colors::GREEN);
+    draw_text_center(disp, "Back", [121, 205], sizes::MEDIUM, colors::TEXT_1, colors::BG_3);
+}
+
 #[derive(Debug)]
 pub(crate) struct Gui {
     disp: Display,
@@ -195,10 +218,26 @@ impl Gui {
                 if let Ok(t) = self.telem.try_read() {
                     draw_sensor_panel(&mut self.disp, &t);
                     if self.prev_press == TouchState::Released && Self::in_range(touch.point, (6, 237), (6, 234)) && touch.state != TouchState::Released {
-                        self.left_split = GuiState::MotorView;
+                        self.left_split = GuiState::TuneView;
                     }
                 };
             }
+            GuiState::TuneView => {
+                if let Ok(t) = self.telem.try_read() {
+                    draw_tune_menu(&mut self.disp, &t);
+                }
+                if self.prev_press == TouchState::Released && touch.state != TouchState::Released {
+                    if Self::in_range(touch.point, (9, 234), (8, 64)) {
+                        self.telem.write().tune_request = Some(Axis::Heading);
+                    } else if Self::in_range(touch.point, (9, 234), (66, 122)) {
+                        self.telem.write().tune_request = Some(Axis::Distance);
+                    } else if Self::in_range(touch.point, (9, 234), (124, 176)) {
+                        self.telem.write().save_tune = true;
+                    } else if Self::in_range(touch.point, (9, 234), (178, 232)) {
+                        self.left_split = GuiState::MotorView;
+                    }
+                }
+            }
             GuiState::AutoSelectorOverview => {
                 draw_auto_overview(&mut self.disp);
                 if self.prev_press == TouchState::Released && touch.state != TouchState::Released {
@@ -207,9 +246,12 @@ impl Gui {
                     } else if Self::in_range(touch.point, (9, 120), (82, 232)) {
                         self.telem.write().auto = Autos::Skills;
                         self.left_split = GuiState::MotorView;
-                    } else if Self::in_range(touch.point, (123, 234), (156, 232)) {
+                    } else if Self::in_range(touch.point, (123, 234), (121, 175)) {
                         self.telem.write().auto = Autos::None;
                         self.left_split = GuiState::MotorView;
+                    } else if Self::in_range(touch.point, (123, 234), (177, 232)) {
+                        self.telem.write().auto = Autos::Recorded;
+                        self.left_split = GuiState::MotorView;
                     }
                 }
             }
@@ -254,6 +296,13 @@ impl Gui {
             41.. => colors::RED,
         });
 
+        let problems = self.telem.read().auto_problems;
+        if problems == 0 {
+            normal_bg_text(&mut self.disp, "Autos: OK", [249, 204], colors::GREEN);
+        } else {
+            normal_bg_text(&mut self.disp, &format!("Autos: {problems} problems, see log"), [249, 204], colors::RED);
+        }
+
         self.disp.render();
     }
 
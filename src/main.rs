@@ -3,18 +3,26 @@
 #![feature(nonpoison_rwlock, sync_nonpoison, lock_value_accessors)]
 
 pub mod autos;
+pub mod autotune;
+pub mod characterize;
+pub mod clock;
 pub mod comp;
 pub mod conf;
 pub mod controller;
 pub mod cubreg;
 pub mod gui;
 pub mod log;
+#[cfg(test)]
+pub mod sim;
 pub mod telemetry;
+#[cfg(test)]
 mod tests;
 pub mod tracking;
 pub mod util;
 
+use core::f64;
 use std::{
+    path::Path,
     sync::{Arc, LazyLock, nonpoison::RwLock},
     time::{Duration, Instant},
 };
@@ -24,8 +32,14 @@ use vexide::{controller::ControllerState, peripherals::DynamicPeripherals, prelu
 use crate::{
     autos::{
         auto::{Action, Auto, Autos},
-        chassis::{Chassis, Pid},
+        chassis::Chassis,
+        exit::ExitCondition,
+        transform::Transform,
+        trigger::{Condition, Readings},
     },
+    autotune::{Autotune, Axis},
+    characterize::{Characterization, fit},
+    clock::RealClock,
     comp::AutoHandler,
     conf::Config,
     controller::arcade,
@@ -52,72 +66,100 @@ impl Robot {
         t.update_motor(&self.intake.motor_2, 7);
         t.update_motor(&self.indexer, 8);
         t.update_requested = false;
+        let auto = t.auto;
+        let tune = t.tune_request.take();
+        let save_tune = std::mem::take(&mut t.save_tune);
         drop(drive); drop(t);
+        self.comp.select(auto);
+
+        // Tuning takes over from Driver Control like the characterization does
+        if let Some(axis) = tune {
+            if self.autotune.is_none() && self.characterization.is_none() {
+                self.autotune = Some(Autotune::new(axis, self.chassis.pose.read().pose, self.comp.clock.clone()));
+                self.telem.write().tuning = Some(axis);
+            }
+        }
+        if save_tune {
+            self.save_tune();
+        }
+    }
+
+    // Use the autotuned gains from now on and write them to conf.json
+    fn save_tune(&mut self) {
+        let (heading, distance) = self.telem.read().tune_results;
+        if heading.is_none() && distance.is_none() {
+            log_warn!("Nothing has been tuned yet");
+            return;
+        }
+        if let Some(result) = heading {
+            self.conf.pid.angular = result.gains;
+            self.chassis.angular.set_gains(result.gains);
+        }
+        if let Some(result) = distance {
+            self.conf.pid.linear = result.gains;
+            self.chassis.linear.set_gains(result.gains);
+        }
+        self.conf.save();
+        log_info!("Saved the tuned PID gains to conf.json");
     }
 
     // Update the robot input during the Autonomous Period
     pub fn auto_tick(&mut self) {
         let auto = self.comp.get_auto();
 
-        let (left, right): (f64, f64) = if auto.motion_start.elapsed().as_secs_f64() * 1000.0 >= auto.get_timeout() || auto.exit_state == 2 {
-            auto.motion_start = Instant::now();
-            auto.exit_state = 3;
-            (0.0, 0.0)
-        } else if auto.motion_start.elapsed().as_secs_f64() * 1000.0 >= auto.get_wait() && auto.exit_state == 3 {
-            if auto.current_curve != auto.spline.len() - 1 {
-                auto.current_curve += 1;
-                auto.motion_start = Instant::now();
-                auto.exit_state = 0;
-                auto.close = false;
-            };
-            (0.0, 0.0)
-        } else if auto.exit_state == 3 {
-            (0.0, 0.0)
-        } else {
-            self.chassis.update(auto)
+        let drive_current = {
+            let drive = self.drive.read();
+            let motors = (drive.left_motors.len() + drive.right_motors.len()) as f64;
+            drive.left_motors.iter().chain(&drive.right_motors).map(|m| m.current().unwrap_or_default().abs()).sum::<f64>() / motors
         };
+        let readings = Readings { intake_stalled: self.intake.m1_stalled || self.intake.m2_stalled, distances: self.chassis.pose.read().distances(), drive_current };
+        let step = auto.step(&mut self.chassis, &readings);
 
         self.drive.write().left_motors.iter_mut().for_each(|m| {
-            m.set_voltage(left * m.max_voltage()).ok();
+            m.set_voltage(step.voltages.0 * m.max_voltage()).ok();
         });
         self.drive.write().right_motors.iter_mut().for_each(|m| {
-            m.set_voltage(right * m.max_voltage()).ok();
+            m.set_voltage(step.voltages.1 * m.max_voltage()).ok();
         });
 
-        if auto.current_action < auto.actions.len() {
-            for i in auto.current_action..(auto.actions.len() - 1) {
-                let action = &auto.actions[i];
-                if (action.1 - (auto.current_curve as f64 + auto.curve_t.clamp(0.0, 1.0))).abs() < 0.025 {
-                    match action.0 {
-                        Action::ToggleMatchload => {
-                            self.matchload.toggle().ok();
-                        }
-                        Action::ToggleDescore => {
-                            self.descore.toggle().ok();
-                            self.intake.reset();
-                        }
-                        Action::SpinIntake(v) => {
-                            self.intake.set_voltage(v).ok();
-                        }
-                        Action::StopIntake => {
-                            self.intake.set_voltage(0.0).ok();
-                        }
-                        Action::SpinIndexer(v) => {
-                            self.indexer.set_voltage(v * self.indexer.max_voltage()).ok();
-                        }
-                        Action::StopIndexer => {
-                            self.indexer.set_voltage(0.0).ok();
-                        }
-                        Action::ResetPose(x, y, theta) => self.chassis.set_pose((x, y, theta)),
-                        Action::DistanceReset(s) => self.chassis.pose.write().distance_reset(s),
-                    };
-                } else {
-                    break;
-                }
-            }
+        for action in step.actions {
+            self.run_action(action);
+        }
+    }
+
+    // Stop anything the auto left running, once it's over or the robot is disabled
+    pub fn stop_auto(&mut self) {
+        for action in self.comp.get_auto().cancel_actions() {
+            self.run_action(action);
         }
     }
 
+    pub fn run_action(&mut self, action: Action) {
+        match action {
+            Action::ToggleMatchload => {
+                self.matchload.toggle().ok();
+            }
+            Action::ToggleDescore => {
+                self.descore.toggle().ok();
+                self.intake.reset();
+            }
+            Action::SpinIntake(v) => {
+                self.intake.set_voltage(v).ok();
+            }
+            Action::StopIntake => {
+                self.intake.set_voltage(0.0).ok();
+            }
+            Action::SpinIndexer(v) => {
+                self.indexer.set_voltage(v * self.indexer.max_voltage()).ok();
+            }
+            Action::StopIndexer => {
+                self.indexer.set_voltage(0.0).ok();
+            }
+            Action::ResetPose(x, y, theta) => self.chassis.set_pose((x, y, theta)),
+            Action::DistanceReset(s) => self.chassis.pose.write().distance_reset(s),
+        };
+    }
+
     // Let the driver control the robot during Driver Control
     pub fn driver_tick(&mut self, state: Option<ControllerState>) {
         match state {
@@ -129,6 +171,11 @@ impl Robot {
                     log_debug!("Started Recording");
                 }
 
+                if state.button_down.is_now_pressed() && state.button_left.is_now_pressed() {
+                    self.characterization = Some(Characterization::new(self.comp.clock.clone()));
+                    return;
+                }
+
                 if state.button_right.is_now_pressed() {
                     let mut telem = self.telem.write();
                     telem.selector_active = true;
@@ -148,16 +195,18 @@ impl Robot {
                     m.set_voltage(motor_vals.1 * m.max_voltage()).ok();
                 });
 
-                self.comp.recorded_poses.push((self.telem.read().pose, self.comp.start_time.elapsed().as_millis() as f64));
+                if self.comp.is_recording {
+                    self.comp.recorded_poses.push((self.telem.read().pose, self.comp.elapsed().as_millis() as f64));
+                }
 
                 if state.button_r1.is_pressed() {
-                    self.comp.recorded_actions.push((Action::SpinIntake(1.00), self.comp.start_time.elapsed().as_millis() as f64));
+                    self.comp.record_action(Action::SpinIntake(1.00));
                     self.intake.set_voltage(1.0).ok();
                 } else if state.button_r2.is_pressed() {
-                    self.comp.recorded_actions.push((Action::SpinIntake(-1.00), self.comp.start_time.elapsed().as_millis() as f64));
+                    self.comp.record_action(Action::SpinIntake(-1.00));
                     self.intake.set_voltage(-1.0).ok();
                 } else {
-                    self.comp.recorded_actions.push((Action::StopIntake, self.comp.start_time.elapsed().as_millis() as f64));
+                    self.comp.record_action(Action::StopIntake);
                     self.intake.set_voltage(0.0).ok();
                 }
 
@@ -165,13 +214,13 @@ impl Robot {
                 self.indexer
                     .set_voltage(
                         if state.button_l1.is_pressed() {
-                            self.comp.recorded_actions.push((Action::SpinIndexer(1.00), self.comp.start_time.elapsed().as_millis() as f64));
+                            self.comp.record_action(Action::SpinIndexer(1.00));
                             1.0
                         } else if state.button_l2.is_pressed() {
-                            self.comp.recorded_actions.push((Action::SpinIndexer(-1.00), self.comp.start_time.elapsed().as_millis() as f64));
+                            self.comp.record_action(Action::SpinIndexer(-1.00));
                             -1.0
                         } else {
-                            self.comp.recorded_actions.push((Action::StopIndexer, self.comp.start_time.elapsed().as_millis() as f64));
+                            self.comp.record_action(Action::StopIndexer);
                             0.0
                         } * self.indexer.max_voltage(),
                     )
@@ -179,12 +228,12 @@ impl Robot {
 
                 // Toggle the Solenoid for the Scraper if B is pressed
                 if state.button_x.is_now_pressed() {
-                    self.comp.recorded_actions.push((Action::ToggleMatchload, self.comp.start_time.elapsed().as_millis() as f64));
+                    self.comp.record_action(Action::ToggleMatchload);
                     self.matchload.toggle().ok();
                 }
 
                 if state.button_b.is_now_pressed() {
-                    self.comp.recorded_actions.push((Action::ToggleDescore, self.comp.start_time.elapsed().as_millis() as f64));
+                    self.comp.record_action(Action::ToggleDescore);
                     self.descore.toggle().ok();
                     self.intake.reset();
                 }
@@ -202,6 +251,93 @@ impl Robot {
     }
 }
 
+impl Robot {
+    // Run the Drivetrain characterization instead of Driver Control, A stops it
+    // early
+    pub fn characterize_tick(&mut self, state: Option<ControllerState>) {
+        let Some(characterization) = self.characterization.as_mut() else {
+            return;
+        };
+        // Average motor speed on each side (rpm) as wheel speed (in/s)
+        // 1.21875 = 1.625 (wheel radius) * 36/48 (gear ratio)
+        let speed = |motors: &[Motor; 3]| {
+            let speeds: Vec<f64> = motors.iter().filter_map(|m| m.velocity().ok()).collect();
+            speeds.iter().sum::<f64>() / speeds.len().max(1) as f64 * f64::consts::TAU / 60.0 * 1.21875
+        };
+        let drive = self.drive.read();
+        let velocity = (speed(&drive.left_motors), speed(&drive.right_motors));
+        drop(drive);
+        let heading = self.chassis.pose.read().imu_heading();
+
+        let stopped = state.is_some_and(|state| state.button_a.is_now_pressed());
+        let voltages = if stopped { None } else { characterization.update(velocity, heading) };
+        let voltages = match voltages {
+            Some(voltages) => voltages,
+            None => {
+                if stopped {
+                    log_warn!("Stopped the characterization early");
+                }
+                match characterization.save(Path::new("characterization.json")) {
+                    Ok(()) => log_info!("Saved {} characterization samples", characterization.samples.len()),
+                    Err(e) => log_warn!("Couldn't save the characterization, {e}"),
+                }
+                // A first look at the gains, the saved samples can be fitted again on a computer
+                match fit(&characterization.samples) {
+                    Some(fit) => log_info!("Characterization fit, {fit}"),
+                    None => log_warn!("Not enough characterization samples to fit"),
+                }
+                self.characterization = None;
+                (0.0, 0.0)
+            }
+        };
+
+        self.set_drive_voltages(voltages);
+    }
+
+    // Run the PID autotune instead of Driver Control, A stops it early
+    pub fn autotune_tick(&mut self, state: Option<ControllerState>) {
+        let Some(autotune) = self.autotune.as_mut() else {
+            return;
+        };
+        let pose = self.chassis.pose.read().pose;
+
+        let stopped = state.is_some_and(|state| state.button_a.is_now_pressed());
+        let voltages = if stopped { None } else { autotune.update(pose) };
+        let voltages = match voltages {
+            Some(voltages) => voltages,
+            None => {
+                if stopped {
+                    log_warn!("Stopped tuning early");
+                }
+                let mut telem = self.telem.write();
+                if let Some(result) = autotune.result() {
+                    match autotune.axis {
+                        Axis::Heading => telem.tune_results.0 = Some(result),
+                        Axis::Distance => telem.tune_results.1 = Some(result),
+                    }
+                }
+                telem.tuning = None;
+                drop(telem);
+                self.autotune = None;
+                (0.0, 0.0)
+            }
+        };
+
+        self.set_drive_voltages(voltages);
+    }
+
+    // Apply (left, right) voltages, as fractions of the maximum, to the
+    // Drivetrain
+    fn set_drive_voltages(&mut self, voltages: (f64, f64)) {
+        self.drive.write().left_motors.iter_mut().for_each(|m| {
+            m.set_voltage(voltages.0 * m.max_voltage()).ok();
+        });
+        self.drive.write().right_motors.iter_mut().for_each(|m| {
+            m.set_voltage(voltages.1 * m.max_voltage()).ok();
+        });
+    }
+}
+
 impl Compete for Robot {
     // Autonomous Loop when the Competition Switch is connected
     async fn connected(&mut self) {
@@ -211,12 +347,15 @@ impl Compete for Robot {
     }
 
     async fn disabled(&mut self) {
+        self.stop_auto();
         self.chassis.calibrate((0.0, 0.0, 0.0)).await;
     }
 
     async fn autonomous(&mut self) {
         log_info!("Running the Autonomous Loop");
-        self.comp.start_time = Instant::now();
+        let auto = self.telem.read().auto;
+        self.comp.select(auto);
+        self.comp.start_time = self.comp.clock.now();
         self.chassis.set_pose(self.comp.get_auto().start_pose);
         self.chassis.reset();
         self.drive.write().left_motors.iter_mut().for_each(|m| {
@@ -225,13 +364,13 @@ impl Compete for Robot {
         self.drive.write().right_motors.iter_mut().for_each(|m| {
             m.brake(BrakeMode::Brake).ok();
         });
-        let mut last_update = Instant::now();
+        let mut last_update = self.comp.clock.now();
         let mut now;
         self.comp.get_auto().reset_state();
         self.comp.get_auto().curve_t = 0.0;
         self.comp.get_auto().current_curve = 0;
         loop {
-            now = Instant::now();
+            now = self.comp.clock.now();
             self.comp.update(now.duration_since(last_update));
             last_update = now;
             // Run auto tick
@@ -248,26 +387,35 @@ impl Compete for Robot {
     // CompController when the Competition Switch is disconnected
     async fn driver(&mut self) {
         log_info!("Running the Drive Loop");
-        self.comp.start_time = Instant::now();
+        self.stop_auto();
+        self.comp.start_time = self.comp.clock.now();
         self.drive.write().left_motors.iter_mut().for_each(|m| {
             m.brake(BrakeMode::Coast).ok();
         });
         self.drive.write().right_motors.iter_mut().for_each(|m| {
             m.brake(BrakeMode::Coast).ok();
         });
-        let mut last_update = Instant::now();
-        let mut countdown_start = Instant::now();
+        let mut last_update = self.comp.clock.now();
+        let mut countdown_start = self.comp.clock.now();
         loop {
-            self.comp.update(last_update.elapsed());
-            last_update = Instant::now();
-            if self.comp.start_recording && countdown_start.elapsed().as_millis() > 3500 {
-                countdown_start = Instant::now();
-            } else if self.comp.start_recording && countdown_start.elapsed().as_millis() > 2990 {
+            self.comp.update(self.comp.clock.elapsed(last_update));
+            last_update = self.comp.clock.now();
+            if self.comp.start_recording && self.comp.clock.elapsed(countdown_start).as_millis() > 3500 {
+                countdown_start = self.comp.clock.now();
+            } else if self.comp.start_recording && self.comp.clock.elapsed(countdown_start).as_millis() > 2990 {
                 self.comp.is_recording = true;
                 self.comp.start_recording = false;
+                // The recording is timed like an auto, from when it starts
+                self.comp.start_time = self.comp.clock.now();
             }
             // Get the Controller's current State
-            self.driver_tick(self.cont.state().ok());
+            if self.characterization.is_some() {
+                self.characterize_tick(self.cont.state().ok());
+            } else if self.autotune.is_some() {
+                self.autotune_tick(self.cont.state().ok());
+            } else {
+                self.driver_tick(self.cont.state().ok());
+            }
             if self.telem.read().update_requested {
                 self.update_telemetry();
             }
@@ -277,6 +425,79 @@ impl Compete for Robot {
     }
 }
 
+/// Exit for pushing into a goal, which stops the robot short of the end point
+/// or heading before it can settle \
+/// Exits within 1 in / deg, or once the drive draws over 2 A while the error
+/// changes slower than 1 in/s / deg/s for 250 ms, stalled against the goal
+fn pushed_into_goal() -> ExitCondition { ExitCondition::Any(vec![ExitCondition::Within(1.0, 0.0), ExitCondition::Stalled(2.0, 1.0, 250.0)]) }
+
+/// Take the blocks from the left loader and back into the long goal to score
+/// them, leaving the indexer running \
+/// Starts lined up with the loader, facing it, and takes 4 segments \
+/// `loader_x` is where to stop against the loader (in) and `load_time` is how
+/// long to wait there (ms)
+fn matchload_and_score(loader_x: f64, load_time: f64) -> impl FnOnce(&mut Auto) {
+    move |auto| {
+        auto.add_action(Action::ToggleMatchload, 0.0);
+        auto.move_to_pose(loader_x, 47.0, 270.0);
+        auto.wait_for(load_time);
+        auto.move_to_pose(-30.0, 47.0, 270.0).reverse();
+        auto.add_action(Action::ToggleDescore, 3.0);
+        auto.add_action(Action::SpinIndexer(1.0), 3.0);
+        auto.add_action(Action::ToggleMatchload, 3.0);
+        auto.wait_for(1000.0);
+    }
+}
+
+/// 9 Block Long Goal + Wing, from the left side of the field
+fn left_elims() -> Auto {
+    let mut auto = Auto::new();
+    auto.start_pose = (-48.0, 16.0, 0.0);
+    auto.add_action(Action::SpinIntake(1.0), 0.0);
+    auto.move_to_pose(-48.0, 47.0, 270.0);
+    auto.include(matchload_and_score(-55.0, 1000.0), None);
+    auto.add_action(Action::StopIndexer, 5.0);
+    auto.add_action(Action::ToggleDescore, 5.0);
+    auto.move_to_pose(-36.0, 36.0, 135.0);
+    // Only go for the center goal if its blocks are still in front of us, otherwise
+    // skip straight to the long goal
+    auto.branch(
+        Condition::DistanceBelow(2, 12.0),
+        |auto| {
+            auto.move_to_pose(-28.5, 28.5, 135.0);
+            auto.add_action(Action::ToggleMatchload, 1.0);
+            auto.move_to_pose(-19.0, 19.0, 315.0);
+            auto.add_action(Action::ToggleMatchload, 2.0);
+            auto.move_to_pose(-13.0, 13.0, 315.0).reverse().exit_when(pushed_into_goal()).heading_exit_when(pushed_into_goal());
+            auto.add_action(Action::SpinIntake(-0.5), 3.0);
+            auto.wait_for(1000.0);
+            auto.add_action(Action::StopIntake, 4.0);
+        },
+        |_| {},
+    );
+    auto.move_to_pose(-25.0, 39.0, 90.0);
+    auto.add_action(Action::ToggleDescore, 11.0);
+    auto.move_to_pose(-12.5, 39.0, 90.0).exit_when(pushed_into_goal());
+    auto
+}
+
+/// 9 Block Half AWP + Wing, from the left side of the field
+fn left_qual() -> Auto {
+    let mut auto = Auto::new();
+    auto.start_pose = (-54.0, 16.0, 90.0);
+    auto.add_action(Action::SpinIntake(1.0), 0.0);
+    auto.move_to_pose(-28.0, 16.0, 45.0);
+    auto.add_action(Action::ToggleMatchload, 1.0);
+    auto.move_to_pose(-16.0, 28.0, 300.0).max_speed(0.75);
+    auto.add_action(Action::ToggleMatchload, 2.0);
+    auto.move_to_pose(-47.0, 47.0, 270.0);
+    auto.include(matchload_and_score(-56.0, 1000.0), None);
+    auto.move_to_pose(-35.0, 39.0, 90.0);
+    auto.add_action(Action::ToggleDescore, 8.0);
+    auto.move_to_pose(-12.0, 39.0, 90.0);
+    auto
+}
+
 pub(crate) fn setup_autos(mut comp: AutoHandler) -> AutoHandler {
     let mut no = Auto::new();
     no.start_pose = (0.0, 0.0, 0.0);
@@ -288,106 +509,15 @@ pub(crate) fn setup_autos(mut comp: AutoHandler) -> AutoHandler {
     no.move_to_pose(0.0, 0.0, 0.0).timeout(1000.0).force_stanley();
     comp.autos.push((Autos::None, no));
 
-    // left_elims
-    let mut left_elims = Auto::new();
-    left_elims.start_pose = (-48.0, 16.0, 0.0);
-    left_elims.add_action(Action::SpinIntake(1.0), 0.0);
-    left_elims.move_to_pose(-48.0, 47.0, 270.0);
-    left_elims.add_action(Action::ToggleMatchload, 1.0);
-    left_elims.move_to_pose(-55.0, 47.0, 270.0);
-    left_elims.wait_for(1.0);
-    left_elims.move_to_pose(-30.0, 47.0, 270.0).reverse();
-    left_elims.add_action(Action::ToggleDescore, 4.0);
-    left_elims.add_action(Action::SpinIndexer(1.0), 4.0);
-    left_elims.add_action(Action::ToggleMatchload, 4.0);
-    left_elims.wait_for(1.0);
-    left_elims.add_action(Action::StopIndexer, 5.0);
-    left_elims.add_action(Action::ToggleDescore, 5.0);
-    left_elims.move_to_pose(-36.0, 36.0, 135.0);
-    left_elims.move_to_pose(-28.5, 28.5, 135.0);
-    left_elims.add_action(Action::ToggleMatchload, 7.0);
-    left_elims.move_to_pose(-19.0, 19.0, 315.0);
-    left_elims.add_action(Action::ToggleMatchload, 8.0);
-    left_elims.move_to_pose(-13.0, 13.0, 315.0).reverse();
-    left_elims.add_action(Action::SpinIntake(-0.5), 9.0);
-    left_elims.wait_for(1.0);
-    left_elims.add_action(Action::StopIntake, 10.0);
-    left_elims.move_to_pose(-25.0, 39.0, 90.0);
-    left_elims.add_action(Action::ToggleDescore, 11.0);
-    left_elims.move_to_pose(-12.5, 39.0, 90.0);
-    comp.autos.push((Autos::LeftElims, left_elims));
-
-    // right_elims
-    let mut right_elims = Auto::new();
-    right_elims.start_pose = (-48.0, -16.0, 180.0);
-    right_elims.add_action(Action::SpinIntake(1.0), 0.0);
-    right_elims.move_to_pose(-48.0, -47.0, 270.0);
-    right_elims.add_action(Action::ToggleMatchload, 1.0);
-    right_elims.move_to_pose(-55.0, -47.0, 270.0);
-    right_elims.wait_for(1.0);
-    right_elims.move_to_pose(-30.0, -47.0, 270.0).reverse();
-    right_elims.add_action(Action::ToggleDescore, 4.0);
-    right_elims.add_action(Action::SpinIndexer(1.0), 4.0);
-    right_elims.add_action(Action::ToggleMatchload, 4.0);
-    right_elims.wait_for(1.0);
-    right_elims.add_action(Action::StopIndexer, 5.0);
-    right_elims.add_action(Action::ToggleDescore, 5.0);
-    right_elims.move_to_pose(-36.0, -36.0, 45.0);
-    right_elims.move_to_pose(-28.5, -28.5, 45.0);
-    right_elims.add_action(Action::ToggleMatchload, 7.0);
-    right_elims.move_to_pose(-19.0, -19.0, 45.0).max_speed(0.75);
-    right_elims.add_action(Action::ToggleMatchload, 8.0);
-    right_elims.move_to_pose(-13.0, -13.0, 45.0);
-    right_elims.add_action(Action::SpinIntake(-0.5), 9.0);
-    right_elims.wait_for(1.0);
-    right_elims.add_action(Action::StopIntake, 10.0);
-    right_elims.move_to_pose(-25.0, -39.0, 90.0).reverse();
-    right_elims.add_action(Action::ToggleDescore, 11.0);
-    right_elims.move_to_pose(-12.5, -39.0, 90.0);
+    // Each side is written for the left and mirrored onto the right so the two can't drift apart
+    comp.autos.push((Autos::LeftElims, left_elims()));
+    let mut right_elims = left_elims();
+    right_elims.transform(Transform::MirrorX);
     comp.autos.push((Autos::RightElims, right_elims));
 
-    // left_qual
-    let mut left_qual = Auto::new();
-    left_qual.start_pose = (-54.0, 16.0, 90.0);
-    left_qual.add_action(Action::SpinIntake(1.0), 0.0);
-    left_qual.move_to_pose(-28.0, 16.0, 45.0);
-    left_qual.add_action(Action::ToggleMatchload, 1.0);
-    left_qual.move_to_pose(-16.0, 28.0, 300.0).max_speed(0.75);
-    left_qual.add_action(Action::ToggleMatchload, 2.0);
-    left_qual.move_to_pose(-47.0, 47.0, 270.0);
-    left_qual.add_action(Action::ToggleMatchload, 3.0);
-    left_qual.move_to_pose(-56.0, 47.0, 270.0);
-    left_qual.wait_for(1.0);
-    left_qual.move_to_pose(-30.0, 47.0, 270.0).reverse();
-    left_qual.add_action(Action::ToggleDescore, 6.0);
-    left_qual.add_action(Action::SpinIndexer(1.0), 6.0);
-    left_qual.add_action(Action::ToggleMatchload, 6.0);
-    left_qual.wait_for(1.0);
-    left_qual.move_to_pose(-35.0, 39.0, 90.0);
-    left_qual.add_action(Action::ToggleDescore, 8.0);
-    left_qual.move_to_pose(-12.0, 39.0, 90.0);
-    comp.autos.push((Autos::LeftQual, left_qual));
-
-    // right_qual
-    let mut right_qual = Auto::new();
-    right_qual.start_pose = (-54.0, -16.0, 90.0);
-    right_qual.add_action(Action::SpinIntake(1.0), 0.0);
-    right_qual.move_to_pose(-28.0, -16.0, 135.0);
-    right_qual.add_action(Action::ToggleMatchload, 1.0);
-    right_qual.move_to_pose(-16.0, -28.0, 240.0).max_speed(0.75);
-    right_qual.add_action(Action::ToggleMatchload, 2.0);
-    right_qual.move_to_pose(-47.0, -47.0, 270.0);
-    right_qual.add_action(Action::ToggleMatchload, 3.0);
-    right_qual.move_to_pose(-56.0, -47.0, 270.0);
-    right_qual.wait_for(1.0);
-    right_qual.move_to_pose(-30.0, -47.0, 270.0).reverse();
-    right_qual.add_action(Action::ToggleDescore, 6.0);
-    right_qual.add_action(Action::SpinIndexer(1.0), 6.0);
-    right_qual.add_action(Action::ToggleMatchload, 6.0);
-    right_qual.wait_for(1.0);
-    right_qual.move_to_pose(-35.0, -39.0, 90.0);
-    right_qual.add_action(Action::ToggleDescore, 8.0);
-    right_qual.move_to_pose(-12.0, -39.0, 90.0);
+    comp.autos.push((Autos::LeftQual, left_qual()));
+    let mut right_qual = left_qual();
+    right_qual.transform(Transform::MirrorX);
     comp.autos.push((Autos::RightQual, right_qual));
 
     // sawp
@@ -401,7 +531,7 @@ pub(crate) fn setup_autos(mut comp: AutoHandler) -> AutoHandler {
     sawp.move_to_pose(-30.0, -47.0, 270.0).reverse();
     sawp.add_action(Action::ToggleDescore, 4.0);
     sawp.add_action(Action::SpinIndexer(1.0), 4.0);
-    sawp.wait_for(2.0);
+    sawp.wait_for(2000.0);
     sawp.add_action(Action::ToggleDescore, 5.0);
     sawp.add_action(Action::StopIndexer, 5.0);
     sawp.add_action(Action::ToggleMatchload, 5.0);
@@ -414,7 +544,7 @@ pub(crate) fn setup_autos(mut comp: AutoHandler) -> AutoHandler {
     sawp.move_to_pose(-24.0, 30.0, 330.0).max_speed(0.75);
     sawp.move_to_pose(-11.0, 11.0, 315.0).reverse();
     sawp.add_action(Action::SpinIndexer(-0.5), 10.0);
-    sawp.wait_for(0.5);
+    sawp.wait_for(500.0);
     sawp.add_action(Action::StopIndexer, 11.0);
     sawp.move_to_pose(-47.0, 47.0, 270.0);
     sawp.move_to_pose(-30.0, 47.0, 270.0).reverse();
@@ -470,17 +600,24 @@ async fn main(peripherals: Peripherals) {
     // Create the Devices needed for Tracking
     let sensors = TrackingSensors::new(&mut dyn_peripherals, [11, 14, 15, 17, 18, 19], [0.0, 0.0, 2.0, 2.0, 2.0], [180.0, 0.0, 90.0], [false, false]);
     let tracking = Arc::new(RwLock::new(Tracking::new(sensors, telem.clone(), drive.clone())));
+    tracking.write().track_width = conf.drivetrain.track_width;
 
-    let linear_pid = Pid::new(8.0, 0.0, 20.0, 0.95, 20.0, 0.25, 400.0, 1.0, 2000.0);
-    let angular_pid = Pid::new(8.0, 0.0, 20.0, 0.95, 20.0, 0.5, 400.0, 1.5, 2000.0);
+    // Everything in the control code reads the time from the same clock
+    let clock = RealClock::shared();
 
-    let chassis = Chassis::new(linear_pid, angular_pid, 0.25, tracking.clone());
+    let mut chassis = Chassis::from_config(&conf, tracking.clone());
+    chassis.set_clock(clock.clone());
 
     // Borrow the primary controller for the Competition loop
     let cont = dyn_peripherals.take_primary_controller().unwrap();
 
     log_debug!("Creating Autos");
-    let comp = setup_autos(AutoHandler::new());
+    let mut comp = setup_autos(AutoHandler::new());
+    // Autos saved on the SD card next to conf.json replace the compiled-in ones
+    comp.load_autos(Path::new(""));
+    // Mistakes in the autos show up on the screen instead of on the field
+    telem.write().auto_problems = comp.validate_autos().len();
+    comp.set_clock(clock);
 
     // Initialize the GUI loop
     let mut gui = Gui::new(dyn_peripherals.take_display().unwrap(), telem.clone());
@@ -498,6 +635,8 @@ async fn main(peripherals: Peripherals) {
         chassis,
         comp,
         telem,
+        characterization: None,
+        autotune: None,
     };
 
     // Calibrate the IMU
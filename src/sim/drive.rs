@@ -0,0 +1,257 @@
+use core::f64;
+
+use crate::tracking::OdomReadings;
+
+/// Meters per inch, the models are in SI units while the rest of the code uses
+/// inches
+const M_PER_IN: f64 = 0.0254;
+
+/// Electrical and mechanical constants for a single V5 Smart Motor \
+/// Fields: \
+///  `max_voltage: f64` - voltage the motor can be commanded to (V) \
+///  `free_speed: f64` - unloaded speed at `max_voltage` (rad/s) \
+///  `stall_torque: f64` - torque with the rotor locked at `max_voltage` (N*m) \
+///  `stall_current: f64` - current with the rotor locked, also the firmware
+/// current limit (A) \
+///  `free_current: f64` - current drawn while unloaded (A)
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MotorModel {
+    pub max_voltage: f64,
+    pub free_speed: f64,
+    pub stall_torque: f64,
+    pub stall_current: f64,
+    pub free_current: f64,
+}
+
+impl MotorModel {
+    /// 11W V5 motor with the blue (600 rpm) cartridge
+    pub const fn blue() -> Self {
+        Self {
+            max_voltage: 12.0,
+            free_speed: 600.0 * f64::consts::TAU / 60.0,
+            stall_torque: 0.35,
+            stall_current: 2.5,
+            free_current: 0.1,
+        }
+    }
+
+    /// Winding resistance (Ohm)
+    pub fn resistance(&self) -> f64 { self.max_voltage / self.stall_current }
+
+    /// Torque constant (N*m/A)
+    pub fn kt(&self) -> f64 { self.stall_torque / self.stall_current }
+
+    /// Back-EMF constant (V*s/rad)
+    pub fn ke(&self) -> f64 { (self.max_voltage - self.free_current * self.resistance()) / self.free_speed }
+
+    /// Current drawn at a given voltage and rotor speed, limited to the stall
+    /// current like the motor firmware does
+    pub fn current(&self, voltage: f64, speed: f64) -> f64 { ((voltage - self.ke() * speed) / self.resistance()).clamp(-self.stall_current, self.stall_current) }
+}
+
+/// Physical constants of the robot's Drivetrain \
+/// Fields: \
+///  `motor: MotorModel` - the motors used on each side \
+///  `motors_per_side: f64` - how many motors power each side \
+///  `gear_ratio: f64` - wheel rotations per motor rotation \
+///  `wheel_radius: f64` - drive wheel radius (in) \
+///  `track_width: f64` - effective distance between the left and right wheels (in) \
+///  `mass: f64` - robot mass (kg) \
+///  `inertia: f64` - moment of inertia around the turning center (kg*m^2) \
+///  `rolling_friction: f64` - constant friction force on each side (N) \
+///  `viscous_friction: f64` - friction force per unit of speed on each side (N*s/m) \
+///  `scrub_torque: f64` - torque resisting turning from the wheels scrubbing (N*m) \
+///  `battery_voltage: f64` - unloaded battery voltage (V) \
+///  `battery_resistance: f64` - internal resistance of the battery (Ohm) \
+///  `vertical_offset: f64` - vertical tracking wheel offset from the center (in) \
+///  `horizontal_offset: f64` - horizontal tracking wheel offset from the center (in) \
+///  `vertical_radius: f64` - vertical tracking wheel radius (in) \
+///  `horizontal_radius: f64` - horizontal tracking wheel radius (in)
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DriveModel {
+    pub motor: MotorModel,
+    pub motors_per_side: f64,
+    pub gear_ratio: f64,
+    pub wheel_radius: f64,
+    pub track_width: f64,
+    pub mass: f64,
+    pub inertia: f64,
+    pub rolling_friction: f64,
+    pub viscous_friction: f64,
+    pub scrub_torque: f64,
+    pub battery_voltage: f64,
+    pub battery_resistance: f64,
+    pub vertical_offset: f64,
+    pub horizontal_offset: f64,
+    pub vertical_radius: f64,
+    pub horizontal_radius: f64,
+}
+
+impl Default for DriveModel {
+    fn default() -> Self {
+        Self {
+            motor: MotorModel::blue(),
+            motors_per_side: 3.0,
+            gear_ratio: 36.0 / 48.0,
+            wheel_radius: 1.625,
+            track_width: 10.37,
+            mass: 6.8,
+            inertia: 0.12,
+            // Omni wheels on foam tiles, low enough that the small outputs at the end of a
+            // motion can still push the robot the last fraction of an inch
+            rolling_friction: 1.0,
+            viscous_friction: 1.5,
+            scrub_torque: 0.25,
+            battery_voltage: 12.8,
+            battery_resistance: 0.15,
+            vertical_offset: 0.0,
+            horizontal_offset: 0.0,
+            // Tracking wheel angles are scaled by these in `Tracking::odom_update`
+            vertical_radius: 1.0,
+            horizontal_radius: 2.0,
+        }
+    }
+}
+
+/// Differential drive physics simulation, stepped with the normalized
+/// voltages produced by `Chassis::update` \
+/// The pose uses the same conventions as `Tracking`: inches, with the heading
+/// in radians clockwise from +Y
+#[derive(Debug, Clone)]
+pub(crate) struct SimDrivetrain {
+    pub model: DriveModel,
+    pub pose: (f64, f64, f64),
+    /// Linear speed of the robot's center (in/s)
+    pub velocity: f64,
+    /// Clockwise angular speed (rad/s)
+    pub angular_velocity: f64,
+    /// Current drawn by a single motor on each side (A)
+    pub currents: (f64, f64),
+    /// Battery voltage after sag (V)
+    pub battery: f64,
+    motor_positions: (f64, f64),
+    heading_zero: f64,
+    vertical: f64,
+    horizontal: f64,
+}
+
+impl SimDrivetrain {
+    pub fn new(model: DriveModel, pose: (f64, f64, f64)) -> Self {
+        Self {
+            model,
+            pose,
+            velocity: 0.0,
+            angular_velocity: 0.0,
+            currents: (0.0, 0.0),
+            battery: model.battery_voltage,
+            motor_positions: (0.0, 0.0),
+            heading_zero: pose.2,
+            vertical: 0.0,
+            horizontal: 0.0,
+        }
+    }
+
+    /// Move the robot to a new pose, stopping it and zeroing every sensor
+    pub fn reset(&mut self, pose: (f64, f64, f64)) {
+        *self = Self::new(self.model, pose);
+    }
+
+    /// Zero the sensors without moving the robot, mirroring what
+    /// `Tracking::reset_pose` does to the real sensors
+    pub fn zero_sensors(&mut self) {
+        self.motor_positions = (0.0, 0.0);
+        self.heading_zero = self.pose.2;
+        self.vertical = 0.0;
+        self.horizontal = 0.0;
+    }
+
+    /// Speed of each side of the Drivetrain (in/s)
+    pub fn side_velocities(&self) -> (f64, f64) {
+        let half_track = self.model.track_width / 2.0;
+        (self.velocity + self.angular_velocity * half_track, self.velocity - self.angular_velocity * half_track)
+    }
+
+    /// Average position of the motors on each side, in radians like
+    /// `Tracking::tracking_loop` reads them
+    pub fn motor_positions(&self) -> (f64, f64) { self.motor_positions }
+
+    /// Sensor values for `Tracking::odom_update`
+    pub fn readings(&self) -> OdomReadings {
+        OdomReadings {
+            imu: Some((self.heading_zero - self.pose.2).rem_euclid(f64::consts::TAU)),
+            vertical: Some(self.vertical / self.model.vertical_radius),
+            horizontal: Some(self.horizontal / self.model.horizontal_radius),
+        }
+    }
+
+    /// Advance the simulation by `dt` seconds with each side of the Drivetrain
+    /// commanded to a voltage between -1.0 and 1.0
+    pub fn step(&mut self, left: f64, right: f64, dt: f64) {
+        // Integrate in 1 ms steps so the motor dynamics stay stable
+        let steps = (dt / 1E-3).ceil().max(1.0);
+        let h = dt / steps;
+        for _ in 0..steps as usize {
+            self.substep(left.clamp(-1.0, 1.0), right.clamp(-1.0, 1.0), h);
+        }
+    }
+
+    fn substep(&mut self, left: f64, right: f64, dt: f64) {
+        let m = self.model;
+        let half_track = m.track_width / 2.0 * M_PER_IN;
+        let wheel_radius = m.wheel_radius * M_PER_IN;
+
+        // The motors can't output more than what the battery has left
+        let supply = m.motor.max_voltage.min(self.battery);
+        let (vl, vr) = self.side_velocities();
+        let motor_speed = |v: f64| v * M_PER_IN / wheel_radius / m.gear_ratio;
+
+        // Motor currents and the force they put on the ground
+        let il = m.motor.current(left * supply, motor_speed(vl));
+        let ir = m.motor.current(right * supply, motor_speed(vr));
+        let force = |i: f64| m.motors_per_side * m.motor.kt() * i / m.gear_ratio / wheel_radius;
+        let (drive_l, drive_r) = (force(il), force(ir));
+        // Static friction holds a side still unless it's pushed hard enough, kinetic
+        // friction slows it down once it's moving
+        let friction = |f: f64, v: f64| {
+            let v = v * M_PER_IN;
+            if v.abs() < 1E-3 { -f.clamp(-m.rolling_friction, m.rolling_friction) } else { -v.signum() * m.rolling_friction - m.viscous_friction * v }
+        };
+        let fl = drive_l + friction(drive_l, vl);
+        let fr = drive_r + friction(drive_r, vr);
+
+        // Wheel scrub resists turning in the same way
+        let turn_torque = (fl - fr) * half_track;
+        let scrub = if self.angular_velocity.abs() < 1E-2 { -turn_torque.clamp(-m.scrub_torque, m.scrub_torque) } else { -self.angular_velocity.signum() * m.scrub_torque };
+
+        let accel = (fl + fr) / m.mass / M_PER_IN;
+        let angular_accel = (turn_torque + scrub) / m.inertia;
+
+        self.velocity += accel * dt;
+        self.angular_velocity += angular_accel * dt;
+        // Once the robot has almost stopped, friction holds it still unless the motors
+        // push hard enough to break it free
+        if self.velocity.abs() * M_PER_IN < 1E-3 && (drive_l + drive_r).abs() <= 2.0 * m.rolling_friction {
+            self.velocity = 0.0;
+        }
+        if self.angular_velocity.abs() < 1E-2 && ((drive_l - drive_r) * half_track).abs() <= m.scrub_torque {
+            self.angular_velocity = 0.0;
+        }
+
+        // Battery sag from the total current draw
+        self.battery = m.battery_voltage - m.battery_resistance * m.motors_per_side * (il.abs() + ir.abs());
+        self.currents = (il, ir);
+
+        // Integrate the pose along the arc the robot is driving
+        let delta_theta = self.angular_velocity * dt;
+        let mid_heading = self.pose.2 + delta_theta / 2.0;
+        let distance = self.velocity * dt;
+        self.pose = (self.pose.0 + distance * mid_heading.sin(), self.pose.1 + distance * mid_heading.cos(), (self.pose.2 + delta_theta).rem_euclid(f64::consts::TAU));
+
+        // Integrate the sensors
+        let (vl, vr) = self.side_velocities();
+        self.motor_positions.0 += motor_speed(vl) * dt;
+        self.motor_positions.1 += motor_speed(vr) * dt;
+        self.vertical += distance - m.vertical_offset * delta_theta;
+        self.horizontal -= m.horizontal_offset * delta_theta;
+    }
+}
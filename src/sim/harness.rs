@@ -0,0 +1,225 @@
+use core::f64;
+use std::sync::{Arc, nonpoison::RwLock};
+
+use vexide::{peripherals::DynamicPeripherals, prelude::Peripherals};
+
+use crate::{
+    autos::{
+        auto::{Auto, Autos, SegmentExit},
+        chassis::Chassis,
+        runner::MotionState,
+    },
+    comp::{AutoHandler, MATCH_AUTO_TIME, SKILLS_TIME},
+    conf::Config,
+    sim::{drive::DriveModel, robot::SimRobot},
+    telemetry::Telem,
+    tracking::{Tracking, TrackingSensors},
+    util::Drivetrain,
+};
+
+/// How far off the robot is allowed to end up \
+/// Fields: \
+///  `position: f64` - distance from the end of a segment (in) \
+///  `heading: f64` - error from the end heading of a segment (deg) \
+///  `chained_position: f64` - distance from the end of a chained segment, which
+/// doesn't settle (in)
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Tolerances {
+    pub position: f64 = 2.0,
+    pub heading: f64 = 5.0,
+    pub chained_position: f64 = 6.0,
+}
+
+/// How a single `PathSegment` went
+#[derive(Debug, Clone)]
+pub(crate) struct SegmentReport {
+    pub index: usize,
+    pub exit: SegmentExit,
+    /// Time spent driving the segment, not counting the wait afterwards (ms)
+    pub duration: f64,
+    /// End point and heading (deg) of the segment
+    pub target: (f64, f64, f64),
+    /// Where the simulated robot actually was when it exited, heading in degrees
+    pub end_pose: (f64, f64, f64),
+    /// Where the simulated robot was when it reached the end point and started
+    /// turning to the end heading, heading in degrees
+    pub arrival: Option<(f64, f64, f64)>,
+    /// Time from the start of the segment to the arrival (ms)
+    pub arrival_time: Option<f64>,
+    /// The last of the segment's own exit conditions that was met, whether it
+    /// was the heading one and which condition fired, see `ExitCondition::check`
+    pub condition: Option<(bool, usize)>,
+}
+
+impl SegmentReport {
+    /// Distance (in) and heading (deg) error at the end of the segment
+    pub fn error(&self) -> (f64, f64) {
+        let mut heading_err = (self.end_pose.2 - self.target.2).rem_euclid(360.0);
+        if heading_err > 180.0 {
+            heading_err -= 360.0;
+        }
+        ((self.end_pose.0 - self.target.0).hypot(self.end_pose.1 - self.target.1), heading_err)
+    }
+}
+
+/// Everything that happened during a simulated run of an `Auto`
+#[derive(Debug, Clone)]
+pub(crate) struct AutoReport {
+    pub auto: Autos,
+    /// Simulated time the auto took to finish (ms)
+    pub duration: f64,
+    pub finished: bool,
+    pub segments: Vec<SegmentReport>,
+    /// How many times each action in `Auto::actions` ran, which should be once
+    /// unless a branch skipped its segment
+    pub action_counts: Vec<usize>,
+    pub failures: Vec<String>,
+}
+
+/// Build a `SimRobot` with the same Chassis tuning as `main`
+pub(crate) fn sim_robot() -> SimRobot {
+    let conf = Config::load();
+    // SAFETY: the tests run on the host against vexide's mock SDK, so there's no
+    // hardware behind the ports for two owners to fight over. The devices made
+    // from them are never read or written either, the sim feeds Tracking
+    // straight from `SimSensors`
+    let mut peripherals = DynamicPeripherals::new(unsafe { Peripherals::steal() });
+
+    let drive = Arc::new(RwLock::new(Drivetrain::new(&conf, &mut peripherals)));
+    let telem = Arc::new(RwLock::new(Telem::new(vec![], vec![])));
+    let sensors = TrackingSensors::new(&mut peripherals, [11, 14, 15, 17, 18, 19], [0.0, 0.0, 2.0, 2.0, 2.0], [180.0, 0.0, 90.0], [false, false]);
+    let tracking = Arc::new(RwLock::new(Tracking::new(sensors, telem, drive)));
+
+    SimRobot::new(Chassis::from_config(&conf, tracking), DriveModel::default())
+}
+
+/// `sim_robot` with a light derivative filter, for testing the motion code rather
+/// than the robot's tuning. The robot's PIDs keep 95% of the last derivative every
+/// update, which lags far enough behind in the sim to overshoot its turns, see
+/// `autos_test`
+pub(crate) fn motion_sim_robot() -> SimRobot {
+    let mut robot = sim_robot();
+    robot.chassis.linear.deriv_alpha = 0.05;
+    robot.chassis.angular.deriv_alpha = 0.05;
+    robot
+}
+
+/// Run `auto` from its start pose until it finishes or runs out of time,
+/// stepping the simulation every `dt` seconds
+pub(crate) fn run_auto(robot: &mut SimRobot, kind: Autos, auto: &mut Auto, dt: f64, tolerances: Tolerances) -> AutoReport {
+    let time_limit = if kind == Autos::Skills { SKILLS_TIME } else { MATCH_AUTO_TIME };
+
+    robot.set_pose(auto.start_pose);
+    // Resets the auto's state too
+    auto.set_clock(robot.clock.clone());
+
+    let mut report = AutoReport {
+        auto: kind,
+        duration: 0.0,
+        finished: false,
+        segments: vec![],
+        action_counts: vec![0; auto.actions.len()],
+        failures: vec![],
+    };
+    let mut time = 0.0;
+    let mut segment_start = 0.0;
+    let mut last_curve = auto.current_curve;
+    let mut arrival = None;
+    let mut arrival_time = None;
+    let mut conditions = auto.exit_conditions.len();
+
+    // Give the auto some extra time past the limit so we can tell how late it is
+    while time <= time_limit * 1.5 {
+        let fired = auto.fired.clone();
+        let turning = matches!(auto.state, MotionState::Turning(_));
+        let step = robot.tick(auto, dt);
+        time += dt * 1000.0;
+
+        for (i, count) in report.action_counts.iter_mut().enumerate() {
+            if auto.fired[i] && !fired.get(i).copied().unwrap_or(false) {
+                *count += 1;
+            }
+        }
+
+        if auto.current_curve != last_curve {
+            last_curve = auto.current_curve;
+            segment_start = time;
+            arrival = None;
+            arrival_time = None;
+            conditions = auto.exit_conditions.len();
+        }
+
+        if !turning && matches!(auto.state, MotionState::Turning(_)) {
+            let pose = robot.drive.pose;
+            arrival = Some((pose.0, pose.1, pose.2.to_degrees()));
+            arrival_time = Some(time - segment_start);
+        }
+
+        // Record how the segment went as soon as the robot stops following it
+        if let Some((index, exit)) = step.exited {
+            let segment = &auto.spline[index];
+            let end = segment.curve.sample(1.0);
+            let pose = robot.drive.pose;
+            report.segments.push(SegmentReport {
+                index,
+                exit,
+                duration: time - segment_start,
+                target: (end.0, end.1, segment.end_heading),
+                end_pose: (pose.0, pose.1, pose.2.to_degrees()),
+                arrival,
+                arrival_time,
+                condition: auto.exit_conditions[conditions..].last().map(|&(_, heading, condition)| (heading, condition)),
+            });
+        }
+
+        if auto.is_finished() {
+            report.finished = true;
+            break;
+        }
+    }
+    report.duration = time;
+    robot.stop(auto);
+
+    check(&mut report, auto, time_limit, tolerances);
+    report
+}
+
+/// Run every auto in `comp` on a fresh `SimRobot`
+pub(crate) fn run_all(comp: &mut AutoHandler, dt: f64, tolerances: Tolerances) -> Vec<AutoReport> {
+    comp.autos.iter_mut().map(|(kind, auto)| run_auto(&mut sim_robot(), *kind, auto, dt, tolerances)).collect()
+}
+
+fn check(report: &mut AutoReport, auto: &Auto, time_limit: f64, tolerances: Tolerances) {
+    for segment in &report.segments {
+        let path = &auto.spline[segment.index];
+        // Waits have no timeout, they always exit straight away
+        if path.timeout == 0.0 {
+            continue;
+        }
+        if segment.exit == SegmentExit::Timeout {
+            report.failures.push(format!("segment {} timed out after {:.0} ms at {:.1?}", segment.index, segment.duration, segment.end_pose));
+        }
+        let (dist_err, heading_err) = segment.error();
+        let max_dist = if path.chained { tolerances.chained_position } else { tolerances.position };
+        if dist_err > max_dist {
+            report.failures.push(format!("segment {} diverged: ended {dist_err:.2} in from {:.1?} at {:.1?}", segment.index, segment.target, segment.end_pose));
+        } else if !path.chained && heading_err.abs() > tolerances.heading {
+            report.failures.push(format!("segment {} diverged: ended {heading_err:.1} deg off of {:.1?} at {:.1?}", segment.index, segment.target, segment.end_pose));
+        }
+    }
+
+    if !report.finished {
+        let segment = auto.current_curve;
+        report.failures.push(format!("didn't finish, stuck on segment {segment} after {:.0} ms", report.duration));
+    } else if report.duration > time_limit {
+        report.failures.push(format!("took {:.0} ms, over the {time_limit:.0} ms limit", report.duration));
+    }
+
+    for (i, count) in report.action_counts.iter().enumerate() {
+        let (action, trigger) = auto.actions[i];
+        let expected = trigger.segment(auto.spline.len()).map_or(1, |segment| auto.visited.get(segment).copied().unwrap_or(false) as usize);
+        if *count != expected {
+            report.failures.push(format!("action {i} ({action:?} on {trigger:?}) ran {count} times"));
+        }
+    }
+}
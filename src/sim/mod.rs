@@ -0,0 +1,4 @@
+pub mod drive;
+pub mod harness;
+pub mod robot;
+pub mod sensors;
@@ -0,0 +1,89 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    autos::{
+        auto::{Action, Auto},
+        chassis::Chassis,
+        runner::AutoStep,
+        trigger::Readings,
+    },
+    clock::ManualClock,
+    log_info,
+    sim::{
+        drive::{DriveModel, SimDrivetrain},
+        sensors::SimSensors,
+    },
+};
+
+/// A `Chassis` driving a `SimDrivetrain` instead of real motors, running
+/// autos the same way `Robot::auto_tick` does \
+/// The Chassis reads the time from `clock`, which only moves forwards as the
+/// simulation is stepped
+#[derive(Debug)]
+pub(crate) struct SimRobot {
+    pub chassis: Chassis,
+    pub drive: SimDrivetrain,
+    pub sensors: SimSensors,
+    pub clock: Arc<ManualClock>,
+}
+
+impl SimRobot {
+    pub fn new(mut chassis: Chassis, model: DriveModel) -> Self {
+        let clock = Arc::new(ManualClock::new());
+        chassis.set_clock(clock.clone());
+        Self {
+            chassis,
+            drive: SimDrivetrain::new(model, (0.0, 0.0, 0.0)),
+            sensors: SimSensors::ideal([2.0, 2.0, 2.0], [180.0, 0.0, 90.0]),
+            clock,
+        }
+    }
+
+    /// Place both the simulated robot and the tracked pose at `pose`, with the
+    /// heading in degrees like the autos' start poses
+    pub fn set_pose(&mut self, pose: (f64, f64, f64)) {
+        let pose = (pose.0, pose.1, pose.2.to_radians());
+        self.drive.reset(pose);
+        self.chassis.set_pose(pose);
+        self.chassis.reset();
+    }
+
+    /// Run one update of `auto`, stepping the simulation and the clock by `dt`
+    /// seconds \
+    /// Returns the actions that ran during this update
+    pub fn tick(&mut self, auto: &mut Auto, dt: f64) -> AutoStep {
+        // The intake isn't simulated, so it never stalls
+        let current = (self.drive.currents.0.abs() + self.drive.currents.1.abs()) / 2.0;
+        let readings = Readings { distances: self.chassis.pose.read().distances(), drive_current: current, .. };
+        let step = auto.step(&mut self.chassis, &readings);
+        step.actions.iter().for_each(|action| self.run_action(*action));
+
+        self.drive.step(step.voltages.0, step.voltages.1, dt);
+        self.clock.advance(Duration::from_secs_f64(dt));
+        let frame = self.sensors.read(&self.drive, dt);
+        let mut tracking = self.chassis.pose.write();
+        tracking.odom_update(frame.motors.0, frame.motors.1, frame.odom);
+        tracking.update_dist_values(frame.distance);
+        step
+    }
+
+    /// Cancel anything `auto` left running, like `Robot::stop_auto` \
+    /// Returns the actions that ran to undo it
+    pub fn stop(&mut self, auto: &mut Auto) -> Vec<Action> {
+        let actions = auto.cancel_actions();
+        actions.iter().for_each(|action| self.run_action(*action));
+        actions
+    }
+
+    fn run_action(&mut self, action: Action) {
+        match action {
+            Action::ResetPose(x, y, theta) => {
+                // Only the tracked pose changes, the robot stays where it is
+                self.drive.zero_sensors();
+                self.chassis.set_pose((x, y, theta));
+            }
+            Action::DistanceReset(s) => self.chassis.pose.write().distance_reset(s),
+            action => log_info!("Ran {action:?}"),
+        }
+    }
+}
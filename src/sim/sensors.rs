@@ -0,0 +1,203 @@
+use core::f64;
+
+use crate::{sim::drive::SimDrivetrain, tracking::OdomReadings};
+
+/// Small xorshift random number generator so that noisy simulations are
+/// reproducible from a seed
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self { Self { state: seed.max(1) } }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Uniformly distributed value in [0, 1)
+    pub fn uniform(&mut self) -> f64 { (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64 }
+
+    /// Normally distributed value with a mean of 0 and a standard deviation of
+    /// 1
+    pub fn gaussian(&mut self) -> f64 {
+        let u1 = self.uniform().max(1E-12);
+        let u2 = self.uniform();
+        (-2.0 * u1.ln()).sqrt() * (f64::consts::TAU * u2).cos()
+    }
+}
+
+/// How a sensor's readings differ from the true value \
+/// Fields: \
+///  `noise: f64` - standard deviation of the noise on each reading \
+///  `bias_drift: f64` - how fast the bias random walks, per square root second \
+///  `quantization: f64` - resolution of the readings, 0.0 for none \
+///  `dropout: f64` - chance of any single reading failing, between 0.0 and 1.0 \
+///  `disconnected: bool` - the sensor never returns a reading
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct NoiseModel {
+    pub noise: f64 = 0.0,
+    pub bias_drift: f64 = 0.0,
+    pub quantization: f64 = 0.0,
+    pub dropout: f64 = 0.0,
+    pub disconnected: bool = false,
+}
+
+impl NoiseModel {
+    /// A sensor that reads the true value exactly
+    pub const fn ideal() -> Self { Self { .. } }
+
+    /// A sensor that is unplugged
+    pub const fn disconnected() -> Self { Self { disconnected: true, .. } }
+}
+
+/// A `NoiseModel` along with the state it builds up over time
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SensorModel {
+    pub model: NoiseModel,
+    pub bias: f64,
+}
+
+impl SensorModel {
+    pub fn new(model: NoiseModel) -> Self { Self { model, bias: 0.0 } }
+
+    /// Read the sensor given its true value and the time since the last reading
+    pub fn read(&mut self, rng: &mut Rng, value: f64, dt: f64) -> Option<f64> {
+        if self.model.disconnected {
+            return None;
+        }
+        self.bias += self.model.bias_drift * dt.max(0.0).sqrt() * rng.gaussian();
+        if rng.uniform() < self.model.dropout {
+            return None;
+        }
+        let reading = value + self.bias + self.model.noise * rng.gaussian();
+        Some(if self.model.quantization > 0.0 { (reading / self.model.quantization).round() * self.model.quantization } else { reading })
+    }
+}
+
+/// A simulated V5 Distance Sensor that ray casts against the field perimeter
+/// \
+/// Fields: \
+///  `offset: f64` - distance from the center of the robot to the sensor along
+/// its beam (in) \
+///  `angle: f64` - direction the sensor faces, counter-clockwise from the
+/// robot's right side (deg) \
+///  `max_range: f64` - furthest the sensor can see (mm) \
+///  `sensor: SensorModel` - noise on the distance reading (mm)
+#[derive(Debug, Clone)]
+pub(crate) struct DistanceModel {
+    pub offset: f64,
+    pub angle: f64,
+    pub max_range: f64,
+    pub sensor: SensorModel,
+}
+
+impl DistanceModel {
+    pub fn new(offset: f64, angle: f64, model: NoiseModel) -> Self { Self { offset, angle, max_range: 2000.0, sensor: SensorModel::new(model) } }
+
+    /// Distance from the sensor to the field perimeter in inches, if the beam
+    /// hits it
+    pub fn ray_cast(&self, pose: (f64, f64, f64)) -> Option<f64> {
+        let heading = pose.2 + f64::consts::FRAC_PI_2 - self.angle.to_radians();
+        let dir = (heading.sin(), heading.cos());
+        let origin = (pose.0 + dir.0 * self.offset, pose.1 + dir.1 * self.offset);
+        // Distance along the beam to the X and Y walls it's facing
+        let hit = |p: f64, d: f64| if d.abs() < 1E-9 { f64::INFINITY } else { (72.0 * d.signum() - p) / d };
+        let dist = hit(origin.0, dir.0).min(hit(origin.1, dir.1));
+        if dist.is_finite() && dist >= 0.0 { Some(dist) } else { None }
+    }
+
+    /// Read the sensor as (distance in mm, confidence), like
+    /// `DistanceSensor::object` reports it
+    pub fn read(&mut self, rng: &mut Rng, pose: (f64, f64, f64), dt: f64) -> Option<(f64, f64)> {
+        let true_dist = self.ray_cast(pose)? * 25.4;
+        let dist = self.sensor.read(rng, true_dist, dt)?;
+        if !(0.0..=self.max_range).contains(&dist) {
+            return None;
+        }
+        // The sensor is less sure of itself the further away the object is
+        let confidence = if dist <= 200.0 { 1.0 } else { (1.0 - (dist - 200.0) / (self.max_range - 200.0)).max(0.25) };
+        Some((dist.round(), confidence))
+    }
+}
+
+/// Every sensor read on a single tracking update
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SensorFrame {
+    pub motors: (f64, f64),
+    pub odom: OdomReadings,
+    pub distance: [Option<(f64, f64)>; 3],
+}
+
+/// Simulated stand-ins for everything in `TrackingSensors` plus the drive
+/// IMEs
+#[derive(Debug, Clone)]
+pub(crate) struct SimSensors {
+    pub rng: Rng,
+    pub imu: SensorModel,
+    pub vertical: SensorModel,
+    pub horizontal: SensorModel,
+    pub left_motors: SensorModel,
+    pub right_motors: SensorModel,
+    pub distance_left: DistanceModel,
+    pub distance_right: DistanceModel,
+    pub distance_front: DistanceModel,
+}
+
+impl SimSensors {
+    /// Ideal sensors, with the distance sensors mounted with the same offsets
+    /// and angles that are passed to `TrackingSensors::new`
+    pub fn ideal(offsets: [f64; 3], angles: [f64; 3]) -> Self {
+        Self {
+            rng: Rng::new(934),
+            imu: SensorModel::new(NoiseModel::ideal()),
+            vertical: SensorModel::new(NoiseModel::ideal()),
+            horizontal: SensorModel::new(NoiseModel::ideal()),
+            left_motors: SensorModel::new(NoiseModel::ideal()),
+            right_motors: SensorModel::new(NoiseModel::ideal()),
+            distance_left: DistanceModel::new(offsets[0], angles[0], NoiseModel::ideal()),
+            distance_right: DistanceModel::new(offsets[1], angles[1], NoiseModel::ideal()),
+            distance_front: DistanceModel::new(offsets[2], angles[2], NoiseModel::ideal()),
+        }
+    }
+
+    /// Sensors with roughly the noise of the real hardware
+    pub fn realistic(offsets: [f64; 3], angles: [f64; 3], seed: u64) -> Self {
+        let distance = NoiseModel { noise: 5.0, dropout: 0.02, quantization: 1.0, .. };
+        Self {
+            rng: Rng::new(seed),
+            imu: SensorModel::new(NoiseModel { noise: 0.001, bias_drift: 0.0005, quantization: 0.01_f64.to_radians(), .. }),
+            vertical: SensorModel::new(NoiseModel { noise: 0.0005, quantization: 0.088_f64.to_radians(), .. }),
+            horizontal: SensorModel::new(NoiseModel { noise: 0.0005, quantization: 0.088_f64.to_radians(), .. }),
+            // Blue cartridge IMEs have 300 ticks per revolution
+            left_motors: SensorModel::new(NoiseModel { quantization: f64::consts::TAU / 300.0, .. }),
+            right_motors: SensorModel::new(NoiseModel { quantization: f64::consts::TAU / 300.0, .. }),
+            distance_left: DistanceModel::new(offsets[0], angles[0], distance),
+            distance_right: DistanceModel::new(offsets[1], angles[1], distance),
+            distance_front: DistanceModel::new(offsets[2], angles[2], distance),
+        }
+    }
+
+    /// Read every sensor from the state of the simulated Drivetrain
+    pub fn read(&mut self, drive: &SimDrivetrain, dt: f64) -> SensorFrame {
+        let rng = &mut self.rng;
+        let truth = drive.readings();
+        let motors = drive.motor_positions();
+        // A dropped IME read still has the other motors on that side to average with
+        let left = self.left_motors.read(rng, motors.0, dt).unwrap_or(motors.0);
+        let right = self.right_motors.read(rng, motors.1, dt).unwrap_or(motors.1);
+        SensorFrame {
+            motors: (left, right),
+            odom: OdomReadings {
+                imu: truth.imu.and_then(|h| self.imu.read(rng, h, dt)).map(|h| h.rem_euclid(f64::consts::TAU)),
+                vertical: truth.vertical.and_then(|v| self.vertical.read(rng, v, dt)),
+                horizontal: truth.horizontal.and_then(|h| self.horizontal.read(rng, h, dt)),
+            },
+            distance: [self.distance_left.read(rng, drive.pose, dt), self.distance_right.read(rng, drive.pose, dt), self.distance_front.read(rng, drive.pose, dt)],
+        }
+    }
+}
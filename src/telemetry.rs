@@ -3,7 +3,10 @@ use vexide::{
     smart::SmartDevice,
 };
 
-use crate::autos::auto::Autos;
+use crate::{
+    autos::auto::Autos,
+    autotune::{Axis, TuneResult},
+};
 
 #[allow(unused)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -28,6 +31,12 @@ pub(crate) struct Telem {
     pub offsets: (f64, f64) = (0.0, 0.0),
     pub auto: Autos = Autos::None,
     pub selector_active: bool = false,
+    pub auto_problems: usize = 0,
+    pub tune_request: Option<Axis> = None,
+    pub tuning: Option<Axis> = None,
+    /// Last (heading, distance) autotune results
+    pub tune_results: (Option<TuneResult>, Option<TuneResult>) = (None, None),
+    pub save_tune: bool = false,
     pub update_requested: bool = false
 }
 
@@ -7,7 +7,7 @@ use std::{
 use vexide::{competition::{CompetitionStatus, status}, math::Angle, peripherals::DynamicPeripherals, prelude::*};
 
 use crate::{
-    log_error, log_info, log_warn, telemetry::Telem, util::{Drivetrain, TrackingWheel}
+    clock::{RealClock, SharedClock}, log_error, log_info, log_warn, telemetry::Telem, util::{Drivetrain, TrackingWheel}
 };
 
 #[derive(Debug)]
@@ -50,40 +50,60 @@ impl TrackingSensors {
     }
 }
 
+/// Raw tracking sensor values for a single odometry update \
+/// Fields: \
+///  `imu: Option<f64>` - IMU heading in radians, `None` if the IMU is disconnected / uncalibrated \
+///  `vertical: Option<f64>` - vertical tracking wheel angle in radians, `None` if disconnected \
+///  `horizontal: Option<f64>` - horizontal tracking wheel angle in radians, `None` if disconnected
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct OdomReadings {
+    pub imu: Option<f64>,
+    pub vertical: Option<f64>,
+    pub horizontal: Option<f64>,
+}
+
 #[derive(Debug)]
 pub(crate) struct Tracking {
+    clock: SharedClock,
     last_tick: Instant,
     drive: Arc<RwLock<Drivetrain>>,
     sensors: TrackingSensors,
     imu_calibrated: bool,
     telem: Arc<RwLock<Telem>>,
     pub(crate) pose: (f64, f64, f64),
+    /// Effective track width (in) for the IME heading fallback
+    pub track_width: f64,
     start_heading: f64,
     delta_pose: (f64, f64),
     h0: f64,
     v0: f64,
     l0: f64,
     r0: f64,
-    dist_vals: (f64, f64, f64)
+    dist_vals: (f64, f64, f64),
+    dist_seen: [bool; 3],
 }
 
 impl Tracking {
     pub fn new(sensors: TrackingSensors, telem: Arc<RwLock<Telem>>, drive: Arc<RwLock<Drivetrain>>) -> Tracking {
+        let clock = RealClock::shared();
         // And return the struct
         Tracking {
             telem,
             drive,
             sensors,
             imu_calibrated: false,
-            last_tick: Instant::now(),
+            last_tick: clock.now(),
+            clock,
             pose: (0.0, 0.0, 0.0),
+            track_width: 10.37,
             start_heading: 0.0,
             delta_pose: (0.0, 0.0),
             h0: 0.0,
             v0: 0.0,
             l0: 0.0,
             r0: 0.0,
-            dist_vals: (0.0, 0.0, 0.0)
+            dist_vals: (0.0, 0.0, 0.0),
+            dist_seen: [false; 3],
         }
     }
 
@@ -109,6 +129,11 @@ impl Tracking {
         self.imu_calibrated = true;
     }
 
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.last_tick = clock.now();
+        self.clock = clock;
+    }
+
     pub fn reset_pose(&mut self, reset_pose: (f64, f64, f64)) {
         // Set the new pose
         self.pose = reset_pose;
@@ -139,12 +164,31 @@ impl Tracking {
         self.calibrate_imu().await;
     }
 
+    /// IMU heading in radians clockwise like `pose`, `None` if the IMU is
+    /// disconnected / uncalibrated
+    pub fn imu_heading(&self) -> Option<f64> {
+        if !self.imu_calibrated {
+            return None;
+        }
+        self.sensors.imu.heading().ok().map(|h| (-h.as_radians()).rem_euclid(f64::consts::TAU))
+    }
+
     pub fn odom_tick(&mut self, l1: f64, r1: f64) {
+        // Read the tracking sensors, leaving out any that are disconnected / uncalibrated
+        let readings = OdomReadings {
+            imu: if self.imu_calibrated { self.sensors.imu.heading().ok().map(|h| h.as_radians()) } else { None },
+            vertical: if self.sensors.vertical_track.sens.is_connected() { Some(self.sensors.vertical_track.sens.angle().unwrap_or_default().as_radians()) } else { None },
+            horizontal: if self.sensors.horizontal_track.sens.is_connected() { Some(self.sensors.horizontal_track.sens.angle().unwrap_or_default().as_radians()) } else { None },
+        };
+        self.odom_update(l1, r1, readings);
+    }
+
+    pub fn odom_update(&mut self, l1: f64, r1: f64, readings: OdomReadings) {
         // Fall back to IME heading if the IMU is dc'ed / uncalibrated
-        let heading = if self.imu_calibrated {
-            -self.sensors.imu.heading().unwrap().as_radians().rem_euclid(f64::consts::TAU) + self.start_heading
+        let heading = if let Some(imu) = readings.imu {
+            (-imu.rem_euclid(f64::consts::TAU) + self.start_heading).rem_euclid(f64::consts::TAU)
         } else {
-            ((((l1 - self.l0) * 1.21875) - ((r1 - self.r0) * 1.21875)) / 10.37 + self.pose.2).rem_euclid(f64::consts::TAU)
+            ((((l1 - self.l0) * 1.21875) - ((r1 - self.r0) * 1.21875)) / self.track_width + self.pose.2).rem_euclid(f64::consts::TAU)
         };
         // Get delta theta and LAO
         let mut delta_theta = (heading - self.pose.2).rem_euclid(f64::consts::TAU);
@@ -152,9 +196,8 @@ impl Tracking {
         let lao = self.pose.2 + delta_theta / 2.0;
 
         // Vertical displacement, fall back to IMEs if no vert wheel
-        let delta_dly = if self.sensors.vertical_track.sens.is_connected() {
+        let delta_dly = if let Some(v1) = readings.vertical {
             // Vertical wheel travel
-            let v1 = self.sensors.vertical_track.sens.angle().unwrap_or_default().as_radians();
             let delta_v = v1 - self.v0;
             self.v0 = v1;
 
@@ -180,9 +223,9 @@ impl Tracking {
         };
 
         // Horizontal displacement, return 0 if no horizontal wheel
-        let delta_dlx = if self.sensors.horizontal_track.sens.is_connected() {
+        let delta_dlx = if let Some(h1) = readings.horizontal {
             // Horizontal wheel displacement
-            let h1 = self.sensors.horizontal_track.sens.angle().unwrap_or_default().as_radians() * 2.00;
+            let h1 = h1 * 2.00;
             let delta_h = h1 - self.h0;
             self.h0 = h1;
 
@@ -201,32 +244,36 @@ impl Tracking {
         let delta_dy = (-lao).sin() * delta_dlx + (-lao).cos() * delta_dly;
 
         // Update pose and state vars
-        let new_pose = (self.pose.0 + delta_dx, self.pose.1 + delta_dy, heading + self.start_heading);
+        let new_pose = (self.pose.0 + delta_dx, self.pose.1 + delta_dy, heading);
 
         self.l0 = l1;
         self.r0 = r1;
         self.pose = new_pose;
-        self.delta_pose = (delta_dx, delta_dx);
+        self.delta_pose = (delta_dx, delta_dy);
     }
 
     fn set_pos_dist(&mut self, dist: f64, offset: f64, angle: f64) {
-        let sensor_heading = self.pose.2 - angle.to_radians() + f64::consts::FRAC_PI_2;
-        match sensor_heading.rem_euclid(f64::consts::PI) {
-            0.0..f64::consts::FRAC_PI_2 => {
-                // Reset Positive X
-                let scaled_dist = sensor_heading.cos() * (dist + offset);
-                self.pose.0 = 72.0 + scaled_dist;
-            },
-            f64::consts::FRAC_PI_2..f64::consts::PI => {
-                // Reset Positive Y
-                let scaled_dist = sensor_heading.sin() * (dist + offset);
-                self.pose.1 = 72.0 + scaled_dist;
-            }
-            _ => {}
+        // Direction the sensor is facing on the field, the sensor angle is measured
+        // counter-clockwise from the robot's right side
+        let sensor_heading = self.pose.2 + f64::consts::FRAC_PI_2 - angle.to_radians();
+        let dir = (sensor_heading.sin(), sensor_heading.cos());
+        // Distance from the center of the robot to the wall along the sensor's beam
+        let scaled_dist = dist + offset;
+        if dir.0.abs() >= dir.1.abs() {
+            // Facing one of the X walls
+            self.pose.0 = 72.0 * dir.0.signum() - dir.0 * scaled_dist;
+        } else {
+            // Facing one of the Y walls
+            self.pose.1 = 72.0 * dir.1.signum() - dir.1 * scaled_dist;
         }
     }
 
     pub fn distance_reset(&mut self, sensor: u8) {
+        // Don't reset off of an old value if the sensor can't currently see a wall
+        if !self.dist_seen.get(sensor as usize).copied().unwrap_or(false) {
+            log_warn!("Distance sensor {sensor} has no reading, skipping reset");
+            return;
+        }
         match sensor {
             0 => self.set_pos_dist(self.dist_vals.0, self.sensors.distance_left.1, self.sensors.distance_left.2),
             1 => self.set_pos_dist(self.dist_vals.1, self.sensors.distance_right.1, self.sensors.distance_right.2),
@@ -235,25 +282,36 @@ impl Tracking {
         }
     }
 
-    pub fn update_dist_sensors(&mut self) {
-        if let Ok(Some(obj)) = self.sensors.distance_left.0.object() {
-            self.dist_vals.0 = obj.distance as f64 * (0.5 * obj.confidence + 0.5) + (self.dist_vals.0 * (1.0 - (0.5 * obj.confidence + 0.5)));
-        }
+    /// Filtered left, right and front distance sensor readings (in), `None` for
+    /// sensors that can't currently see a wall
+    pub fn distances(&self) -> [Option<f64>; 3] {
+        let vals = [self.dist_vals.0, self.dist_vals.1, self.dist_vals.2];
+        std::array::from_fn(|i| self.dist_seen[i].then_some(vals[i]))
+    }
 
-        if let Ok(Some(obj)) = self.sensors.distance_right.0.object() {
-            self.dist_vals.1 = obj.distance as f64 * (0.5 * obj.confidence + 0.5) + (self.dist_vals.1 * (1.0 - (0.5 * obj.confidence + 0.5)));
-        }
+    pub fn update_dist_sensors(&mut self) {
+        let read = |sens: &DistanceSensor| sens.object().ok().flatten().map(|obj| (obj.distance as f64, obj.confidence));
+        let readings = [read(&self.sensors.distance_left.0), read(&self.sensors.distance_right.0), read(&self.sensors.distance_front.0)];
+        self.update_dist_values(readings);
+    }
 
-        if let Ok(Some(obj)) = self.sensors.distance_front.0.object() {
-            self.dist_vals.2 = obj.distance as f64 * (0.5 * obj.confidence + 0.5) + (self.dist_vals.2 * (1.0 - (0.5 * obj.confidence + 0.5)));
-        }
+    /// Filter new distance sensor readings of (distance in mm, confidence) for
+    /// the left, right and front sensors, `None` if a sensor didn't see anything
+    pub fn update_dist_values(&mut self, readings: [Option<(f64, f64)>; 3]) {
+        // Trust readings more the more confident the sensor is
+        let filter = |last: f64, reading: Option<(f64, f64)>| match reading {
+            Some((dist, confidence)) => dist / 25.4 * (0.5 * confidence + 0.5) + (last * (1.0 - (0.5 * confidence + 0.5))),
+            None => last,
+        };
+        self.dist_vals = (filter(self.dist_vals.0, readings[0]), filter(self.dist_vals.1, readings[1]), filter(self.dist_vals.2, readings[2]));
+        self.dist_seen = readings.map(|r| r.is_some());
     }
 
     pub async fn tracking_loop(tracking: Arc<RwLock<Tracking>>) {
         loop {
             let mut track = tracking.write();
 
-            track.last_tick = Instant::now();
+            track.last_tick = track.clock.now();
 
             // Only update odom & GUI if the robot can move
             if !status().contains(CompetitionStatus::DISABLED) {
@@ -275,7 +333,7 @@ impl Tracking {
                 }
             }
             // Get runtime to sleep the loop
-            let dt = track.last_tick.elapsed();
+            let dt = track.clock.elapsed(track.last_tick);
             drop(track);
             sleep(Duration::from_secs_f64((0.007 - dt.as_secs_f64()).max(0.0))).await;
         }
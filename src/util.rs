@@ -2,7 +2,7 @@ use std::sync::{nonpoison::RwLock, Arc};
 
 use vexide::{peripherals::DynamicPeripherals, prelude::*, smart::PortError};
 
-use crate::{autos::chassis::Chassis, comp::AutoHandler, conf::Config, telemetry::Telem};
+use crate::{autos::chassis::Chassis, autotune::Autotune, characterize::Characterization, comp::AutoHandler, conf::Config, telemetry::Telem};
 
 #[derive(Debug)]
 pub(crate) struct TrackingWheel {
@@ -91,6 +91,8 @@ pub(crate) struct Robot {
     pub chassis: Chassis,
     pub comp: AutoHandler,
     pub telem: Arc<RwLock<Telem>>,
+    pub characterization: Option<Characterization>,
+    pub autotune: Option<Autotune>,
 }
 
 pub fn mag(v: (f64, f64)) -> f64 { v.0.hypot(v.1) }